@@ -71,7 +71,7 @@ pub struct Poly {
 type Point = Obj;
 type Ellipse = Obj;
 
-#[derive(Eq, PartialEq)]
+#[derive(Eq, PartialEq, Clone, Copy)]
 pub enum TextHorizontalAlign {
     Left,
     Center,
@@ -79,7 +79,7 @@ pub enum TextHorizontalAlign {
     Justify
 }
 
-#[derive(Eq, PartialEq)]
+#[derive(Eq, PartialEq, Clone, Copy)]
 pub enum TextVerticalAlign {
     Top,
     Center,
@@ -0,0 +1,246 @@
+//! named clips of frames that are stepped by the frame time and drawn as a `GameObject`
+
+use crate::{GameObject, resource};
+use geometry::Rect;
+
+use std::collections::HashMap;
+
+/// a part of a texture shown for `duration` seconds
+#[derive(Clone, Copy)]
+pub struct Frame {
+    pub texture: resource::Texture,
+    /// relative to the texture
+    pub rect: Rect,
+    pub duration: f64,
+}
+
+impl Frame {
+    /// a frame showing the whole texture
+    pub fn new(texture: resource::Texture, duration: f64) -> Frame {
+        Frame {
+            texture,
+            rect: Rect::new(0.0, 0.0, texture.width as f64, texture.height as f64),
+            duration,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PlayMode {
+    Loop,
+    /// play forwards then backwards, repeating
+    PingPong,
+    /// stop on the last frame
+    Once,
+}
+
+#[derive(Clone)]
+pub struct Clip {
+    pub frames: Vec<Frame>,
+    pub mode: PlayMode,
+}
+
+impl Clip {
+    pub fn new(frames: Vec<Frame>, mode: PlayMode) -> Clip {
+        Clip { frames, mode }
+    }
+}
+
+/// plays one of its clips at a time
+#[derive(Clone)]
+pub struct Animation {
+    clips: HashMap<String, Clip>,
+    current: String,
+    frame: usize,
+    time: f64,
+    forward: bool,
+    finished: bool,
+    /// how fast clips play, 1 is the speed of their frame durations
+    pub speed: f64,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Animation {
+    pub fn new() -> Animation {
+        Animation {
+            clips: HashMap::new(),
+            current: String::new(),
+            frame: 0,
+            time: 0.0,
+            forward: true,
+            finished: false,
+            speed: 1.0,
+        }
+    }
+
+    /// the first clip added starts playing, replacing the clip that is playing starts it again
+    pub fn add_clip(&mut self, name: &str, clip: Clip) {
+        self.clips.insert(name.to_string(), clip);
+        if self.current.is_empty() {
+            self.play(name);
+        } else if self.current == name {
+            self.restart();
+        }
+    }
+
+    /// switch to a clip from its first frame, does nothing if it is already playing
+    pub fn play(&mut self, name: &str) {
+        if self.current == name {
+            return;
+        }
+        self.current = name.to_string();
+        self.restart();
+    }
+
+    pub fn restart(&mut self) {
+        self.frame = 0;
+        self.time = 0.0;
+        self.forward = true;
+        self.finished = false;
+    }
+
+    pub fn playing(&self) -> &str {
+        &self.current
+    }
+
+    /// whether a `PlayMode::Once` clip has reached its last frame
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn update(&mut self, timer: f64) {
+        let (frames, mode) = match self.clips.get(&self.current) {
+            Some(c) => (c.frames.len(), c.mode),
+            None => return,
+        };
+        if frames == 0 || self.finished {
+            return;
+        }
+        self.time += timer * self.speed;
+        loop {
+            let duration = self.clips[&self.current].frames[self.frame].duration;
+            if self.time < duration || self.finished {
+                break;
+            }
+            self.time -= duration;
+            self.advance(frames, mode);
+            if duration <= 0.0 {
+                // don't skip through every zero length frame in one update
+                break;
+            }
+        }
+    }
+
+    /// the frame being shown, `None` if the clip is missing or empty
+    pub fn frame(&self) -> Option<&Frame> {
+        self.clips.get(&self.current).and_then(|c| c.frames.get(self.frame))
+    }
+
+    /// `base` showing the current frame
+    pub fn object(&self, base: &GameObject) -> GameObject {
+        let mut obj = *base;
+        if let Some(frame) = self.frame() {
+            obj.texture = frame.texture;
+            obj.tex_rect = frame.rect;
+        }
+        obj
+    }
+
+    fn advance(&mut self, frames: usize, mode: PlayMode) {
+        match mode {
+            PlayMode::Loop => self.frame = (self.frame + 1) % frames,
+            PlayMode::Once => {
+                if self.frame + 1 < frames {
+                    self.frame += 1;
+                } else {
+                    self.finished = true;
+                    self.time = 0.0;
+                }
+            },
+            PlayMode::PingPong => {
+                if frames == 1 {
+                    return;
+                }
+                if self.forward && self.frame + 1 == frames {
+                    self.forward = false;
+                } else if !self.forward && self.frame == 0 {
+                    self.forward = true;
+                }
+                if self.forward { self.frame += 1; } else { self.frame -= 1; }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod animation_tests {
+    use super::*;
+
+    /// frames that each show a different column of the texture, so the column says which frame is showing
+    fn clip(count: usize, mode: PlayMode) -> Clip {
+        let texture = resource::Texture { id: 0, x: 0, y: 0, width: 8, height: 1 };
+        let frames = (0..count).map(|i| Frame { texture, rect: Rect::new(i as f64, 0.0, 1.0, 1.0), duration: 0.1 }).collect();
+        Clip::new(frames, mode)
+    }
+
+    /// the frame shown after each of `steps` updates of 0.1 seconds
+    fn steps(anim: &mut Animation, steps: usize) -> Vec<usize> {
+        (0..steps).map(|_| {
+            anim.update(0.1);
+            anim.frame().unwrap().rect.x as usize
+        }).collect()
+    }
+
+    #[test]
+    fn test_loop() {
+        let mut anim = Animation::new();
+        anim.add_clip("walk", clip(3, PlayMode::Loop));
+        assert!(anim.playing() == "walk");
+        assert!(anim.frame().unwrap().rect.x == 0.0);
+        assert!(steps(&mut anim, 5) == vec![1, 2, 0, 1, 2]);
+        // a long frame time steps through every frame it covered
+        anim.update(0.25);
+        assert!(anim.frame().unwrap().rect.x == 1.0);
+        assert!(!anim.finished());
+    }
+
+    #[test]
+    fn test_ping_pong() {
+        let mut anim = Animation::new();
+        anim.add_clip("bounce", clip(3, PlayMode::PingPong));
+        assert!(steps(&mut anim, 6) == vec![1, 2, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn test_once() {
+        let mut anim = Animation::new();
+        anim.add_clip("die", clip(3, PlayMode::Once));
+        anim.speed = 2.0;
+        assert!(steps(&mut anim, 2) == vec![2, 2]);
+        assert!(anim.finished());
+        anim.restart();
+        assert!(!anim.finished() && anim.frame().unwrap().rect.x == 0.0);
+    }
+
+    #[test]
+    fn test_replace_clip() {
+        let mut anim = Animation::new();
+        anim.add_clip("walk", clip(3, PlayMode::Loop));
+        anim.add_clip("idle", clip(1, PlayMode::Loop));
+        assert!(anim.playing() == "walk");
+        steps(&mut anim, 2);
+        // a shorter clip replacing the one playing starts from its first frame
+        anim.add_clip("walk", clip(1, PlayMode::Loop));
+        assert!(anim.frame().unwrap().rect.x == 0.0);
+        anim.play("idle");
+        assert!(anim.playing() == "idle");
+        anim.play("missing");
+        assert!(anim.frame().is_none());
+        anim.update(0.1);
+    }
+}
@@ -0,0 +1,521 @@
+//! reads `.aseprite` files and aseprite's json sprite sheet exports as textures and animation clips
+
+use crate::{resource, TextureManager};
+use crate::animation::{Animation, Clip, Frame, PlayMode};
+use geometry::Rect;
+
+use sdl2::surface::Surface;
+use sdl2::pixels::PixelFormatEnum;
+
+use flate2::read::ZlibDecoder;
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const FILE_MAGIC : u16 = 0xA5E0;
+const FRAME_MAGIC : u16 = 0xF1FA;
+
+const CHUNK_OLD_PALETTE : u16 = 0x0004;
+const CHUNK_LAYER : u16 = 0x2004;
+const CHUNK_CEL : u16 = 0x2005;
+const CHUNK_TAGS : u16 = 0x2018;
+const CHUNK_PALETTE : u16 = 0x2019;
+
+const LAYER_VISIBLE : u16 = 1;
+const LAYER_BACKGROUND : u16 = 8;
+
+const CEL_RAW : u16 = 0;
+const CEL_LINKED : u16 = 1;
+const CEL_COMPRESSED : u16 = 2;
+
+/// the order a tag plays its frames in
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Forward,
+    Reverse,
+    PingPong,
+    PingPongReverse,
+}
+
+/// a named range of frames, each tag becomes an animation clip
+#[derive(Clone, Debug)]
+pub struct Tag {
+    pub name: String,
+    pub from: usize,
+    /// inclusive
+    pub to: usize,
+    pub direction: Direction,
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    pub name: String,
+    /// false if it or a group it is in is hidden
+    pub visible: bool,
+    pub opacity: u8,
+    pub group: bool,
+    background: bool,
+}
+
+/// a layer's image in one frame, as RGBA bytes
+#[derive(Clone)]
+struct Cel {
+    layer: usize,
+    x: i32,
+    y: i32,
+    opacity: u8,
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+/// a decoded `.aseprite` file. Only normal blending is used when layers are combined,
+/// and tilemap layers are skipped
+pub struct Aseprite {
+    pub width: u32,
+    pub height: u32,
+    pub layers: Vec<Layer>,
+    pub tags: Vec<Tag>,
+    /// in seconds
+    pub durations: Vec<f64>,
+    cels: Vec<Vec<Cel>>,
+}
+
+/// where each frame is in a sprite sheet, and the tags that group them
+#[derive(Clone)]
+pub struct SpriteSheet {
+    /// the sheet image for a json export, relative to the working directory
+    pub image: Option<PathBuf>,
+    pub frames: Vec<(Rect, f64)>,
+    pub tags: Vec<Tag>,
+}
+
+impl Aseprite {
+    pub fn load(path: &Path) -> Result<Aseprite, String> {
+        let data = std::fs::read(path).map_err(|e| format!("{}: {}", path.to_string_lossy(), e))?;
+        Aseprite::parse(&data).map_err(|e| format!("{}: {}", path.to_string_lossy(), e))
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Aseprite, String> {
+        let mut header = Bytes::new(data);
+        header.skip(4)?;
+        if header.u16()? != FILE_MAGIC {
+            return Err(String::from("not an aseprite file"));
+        }
+        let frame_count = header.u16()? as usize;
+        let width = header.u16()? as u32;
+        let height = header.u16()? as u32;
+        let depth = header.u16()?;
+        if depth != 32 && depth != 16 && depth != 8 {
+            return Err(format!("unknown colour depth {}", depth));
+        }
+        header.skip(14)?;
+        let transparent = header.u8()?;
+
+        let mut ase = Aseprite {
+            width,
+            height,
+            layers: Vec::new(),
+            tags: Vec::new(),
+            durations: Vec::new(),
+            cels: Vec::new(),
+        };
+        // groups that are visible at each nesting level, while reading layers
+        let mut groups_visible: Vec<bool> = Vec::new();
+        let mut palette = vec![[0u8; 4]; 256];
+        let mut new_palette = false;
+        let mut pos = 128;
+        for frame in 0..frame_count {
+            let mut f = Bytes::new(data.get(pos..).ok_or_else(end_of_data)?);
+            let frame_size = f.u32()? as usize;
+            if f.u16()? != FRAME_MAGIC {
+                return Err(format!("frame {} is corrupt", frame));
+            }
+            let old_chunks = f.u16()? as usize;
+            ase.durations.push(f.u16()? as f64 / 1000.0);
+            f.skip(2)?;
+            let chunks = match f.u32()? as usize {
+                0 => old_chunks,
+                n => n,
+            };
+            let mut cels = Vec::new();
+            for _ in 0..chunks {
+                let size = f.u32()? as usize;
+                let kind = f.u16()?;
+                let mut c = Bytes::new(f.take(size.checked_sub(6).ok_or_else(end_of_data)?)?);
+                match kind {
+                    CHUNK_LAYER => {
+                        let flags = c.u16()?;
+                        let kind = c.u16()?;
+                        let level = c.u16()? as usize;
+                        c.skip(6)?;
+                        let opacity = c.u8()?;
+                        c.skip(3)?;
+                        let name = c.string()?;
+                        groups_visible.truncate(level);
+                        let visible = flags & LAYER_VISIBLE != 0 && groups_visible.iter().all(|v| *v);
+                        if kind == 1 {
+                            groups_visible.push(visible);
+                        }
+                        ase.layers.push(Layer {
+                            name,
+                            visible,
+                            opacity,
+                            group: kind == 1,
+                            background: flags & LAYER_BACKGROUND != 0,
+                        });
+                    },
+                    CHUNK_CEL => {
+                        let layer = c.u16()? as usize;
+                        let x = c.i16()? as i32;
+                        let y = c.i16()? as i32;
+                        let opacity = c.u8()?;
+                        let kind = c.u16()?;
+                        c.skip(7)?;
+                        let background = ase.layers.get(layer).map(|l| l.background).unwrap_or(false);
+                        match kind {
+                            CEL_LINKED => {
+                                let linked = c.u16()? as usize;
+                                if let Some(cel) = ase.cels.get(linked).and_then(|f| f.iter().find(|c| c.layer == layer)) {
+                                    cels.push(Cel { x, y, opacity, ..cel.clone() });
+                                }
+                            },
+                            CEL_RAW | CEL_COMPRESSED => {
+                                let width = c.u16()? as usize;
+                                let height = c.u16()? as usize;
+                                let raw = if kind == CEL_RAW {
+                                    c.rest().to_vec()
+                                } else {
+                                    let mut raw = Vec::new();
+                                    ZlibDecoder::new(c.rest()).read_to_end(&mut raw).map_err(|e| e.to_string())?;
+                                    raw
+                                };
+                                let mut pixels = Vec::with_capacity(width * height * 4);
+                                for p in raw.chunks_exact((depth / 8) as usize).take(width * height) {
+                                    pixels.extend_from_slice(&match depth {
+                                        32 => [p[0], p[1], p[2], p[3]],
+                                        16 => [p[0], p[0], p[0], p[1]],
+                                        _ if p[0] == transparent && !background => [0, 0, 0, 0],
+                                        _ => palette[p[0] as usize],
+                                    });
+                                }
+                                if pixels.len() != width * height * 4 {
+                                    return Err(format!("cel in frame {} is missing pixels", frame));
+                                }
+                                cels.push(Cel { layer, x, y, opacity, width, height, pixels });
+                            },
+                            _ => (),
+                        }
+                    },
+                    CHUNK_TAGS => {
+                        let count = c.u16()?;
+                        c.skip(8)?;
+                        for _ in 0..count {
+                            let from = c.u16()? as usize;
+                            let to = c.u16()? as usize;
+                            let direction = match c.u8()? {
+                                1 => Direction::Reverse,
+                                2 => Direction::PingPong,
+                                3 => Direction::PingPongReverse,
+                                _ => Direction::Forward,
+                            };
+                            c.skip(12)?;
+                            let name = c.string()?;
+                            ase.tags.push(Tag { name, from, to, direction });
+                        }
+                    },
+                    CHUNK_PALETTE => {
+                        new_palette = true;
+                        c.skip(4)?;
+                        let first = c.u32()? as usize;
+                        let last = c.u32()? as usize;
+                        c.skip(8)?;
+                        for i in first..=last {
+                            let flags = c.u16()?;
+                            let colour = [c.u8()?, c.u8()?, c.u8()?, c.u8()?];
+                            if flags & 1 != 0 {
+                                c.string()?;
+                            }
+                            if let Some(entry) = palette.get_mut(i) {
+                                *entry = colour;
+                            }
+                        }
+                    },
+                    // only files from old versions of aseprite don't have the new palette as well
+                    CHUNK_OLD_PALETTE if !new_palette => {
+                        let mut i = 0;
+                        for _ in 0..c.u16()? {
+                            i += c.u8()? as usize;
+                            let count = match c.u8()? {
+                                0 => 256,
+                                n => n as usize,
+                            };
+                            for _ in 0..count {
+                                let colour = [c.u8()?, c.u8()?, c.u8()?, 255];
+                                if let Some(entry) = palette.get_mut(i) {
+                                    *entry = colour;
+                                }
+                                i += 1;
+                            }
+                        }
+                    },
+                    _ => (),
+                }
+            }
+            ase.cels.push(cels);
+            pos += frame_size;
+        }
+        Ok(ase)
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.durations.len()
+    }
+
+    /// a frame as RGBA bytes, with every visible layer combined, or just the layer called `layer`
+    pub fn frame_pixels(&self, frame: usize, layer: Option<&str>) -> Vec<u8> {
+        let (w, h) = (self.width as i32, self.height as i32);
+        let mut out = vec![0u8; (w * h * 4) as usize];
+        let mut cels: Vec<&Cel> = self.cels[frame].iter().collect();
+        cels.sort_by_key(|c| c.layer);
+        for cel in cels {
+            let info = &self.layers[cel.layer];
+            let shown = match layer {
+                Some(name) => info.name == name,
+                None => info.visible,
+            };
+            if !shown {
+                continue;
+            }
+            let opacity = info.opacity as f32 / 255.0 * cel.opacity as f32 / 255.0;
+            for y in 0..cel.height as i32 {
+                for x in 0..cel.width as i32 {
+                    let (px, py) = (cel.x + x, cel.y + y);
+                    if px < 0 || py < 0 || px >= w || py >= h {
+                        continue;
+                    }
+                    let src = &cel.pixels[(y as usize * cel.width + x as usize) * 4..][..4];
+                    let dst = &mut out[((py * w + px) * 4) as usize..][..4];
+                    blend(dst, src, opacity);
+                }
+            }
+        }
+        out
+    }
+
+    /// every frame in a grid on one surface, with where each one is
+    pub fn sheet(&self, layer: Option<&str>) -> Result<(Surface<'static>, SpriteSheet), String> {
+        let count = self.frame_count().max(1);
+        let columns = (count as f64).sqrt().ceil() as u32;
+        let rows = (count as u32).div_ceil(columns);
+        let mut surface = Surface::new(self.width * columns, self.height * rows, PixelFormatEnum::ABGR8888)?;
+        let pitch = surface.pitch() as usize;
+        let row = self.width as usize * 4;
+        let mut frames = Vec::new();
+        let mut images = Vec::new();
+        for i in 0..self.frame_count() {
+            let (x, y) = (i as u32 % columns * self.width, i as u32 / columns * self.height);
+            frames.push((Rect::new(x as f64, y as f64, self.width as f64, self.height as f64), self.durations[i]));
+            images.push((x as usize, y as usize, self.frame_pixels(i, layer)));
+        }
+        surface.with_lock_mut(|data| {
+            for (x, y, pixels) in images.iter() {
+                for (r, line) in pixels.chunks(row).enumerate() {
+                    let start = (y + r) * pitch + x * 4;
+                    data[start..start + row].copy_from_slice(line);
+                }
+            }
+        });
+        Ok((surface, SpriteSheet { image: None, frames, tags: self.tags.clone() }))
+    }
+}
+
+impl SpriteSheet {
+    /// read the json aseprite writes when exporting a sprite sheet, with frames as a hash or an array.
+    /// Export without trimming or rotation, since frames are drawn with the sheet's rect as they are
+    pub fn load_json(path: &Path) -> Result<SpriteSheet, String> {
+        let err = |e: &str| format!("{}: {}", path.to_string_lossy(), e);
+        let text = std::fs::read_to_string(path).map_err(|e| err(&e.to_string()))?;
+        let json: serde_json::Value = serde_json::from_str(&text).map_err(|e| err(&e.to_string()))?;
+        let frames: Vec<&serde_json::Value> = match &json["frames"] {
+            serde_json::Value::Array(a) => a.iter().collect(),
+            serde_json::Value::Object(o) => o.values().collect(),
+            _ => return Err(err("missing frames")),
+        };
+        let num = |v: &serde_json::Value, key: &str| v[key].as_f64().ok_or_else(|| err(&format!("missing {}", key)));
+        let mut sheet = SpriteSheet { image: None, frames: Vec::new(), tags: Vec::new() };
+        for frame in frames {
+            if frame["rotated"].as_bool().unwrap_or(false) {
+                return Err(err("rotated frames aren't supported"));
+            }
+            let r = &frame["frame"];
+            sheet.frames.push((
+                Rect::new(num(r, "x")?, num(r, "y")?, num(r, "w")?, num(r, "h")?),
+                num(frame, "duration")? / 1000.0,
+            ));
+        }
+        let meta = &json["meta"];
+        if let Some(image) = meta["image"].as_str() {
+            sheet.image = Some(path.parent().unwrap_or(Path::new("")).join(image));
+        }
+        for tag in meta["frameTags"].as_array().into_iter().flatten() {
+            sheet.tags.push(Tag {
+                name: tag["name"].as_str().unwrap_or("").to_string(),
+                from: num(tag, "from")? as usize,
+                to: num(tag, "to")? as usize,
+                direction: match tag["direction"].as_str() {
+                    Some("reverse") => Direction::Reverse,
+                    Some("pingpong") => Direction::PingPong,
+                    Some("pingpong_reverse") => Direction::PingPongReverse,
+                    _ => Direction::Forward,
+                },
+            });
+        }
+        Ok(sheet)
+    }
+
+    /// a clip for each tag, or one clip called "default" with every frame if there are no tags
+    pub fn animation(&self, texture: resource::Texture) -> Animation {
+        let mut anim = Animation::new();
+        let frame = |&(rect, duration): &(Rect, f64)| Frame { texture, rect, duration };
+        if self.tags.is_empty() {
+            anim.add_clip("default", Clip::new(self.frames.iter().map(frame).collect(), PlayMode::Loop));
+        }
+        for tag in self.tags.iter() {
+            let mut frames: Vec<Frame> = self.frames.iter()
+                .skip(tag.from)
+                .take((tag.to + 1).saturating_sub(tag.from))
+                .map(frame)
+                .collect();
+            if tag.direction == Direction::Reverse || tag.direction == Direction::PingPongReverse {
+                frames.reverse();
+            }
+            let mode = match tag.direction {
+                Direction::PingPong | Direction::PingPongReverse => PlayMode::PingPong,
+                _ => PlayMode::Loop,
+            };
+            anim.add_clip(&tag.name, Clip::new(frames, mode));
+        }
+        anim
+    }
+}
+
+/// an animation from a `.aseprite` file, or from a `.json` sprite sheet export and the image it names
+pub fn load_animation<T>(tm: &mut TextureManager<T>, path: &Path) -> Result<Animation, String> {
+    if path.extension().map(|e| e == "json").unwrap_or(false) {
+        let sheet = SpriteSheet::load_json(path)?;
+        let image = sheet.image.clone().ok_or_else(|| format!("{} doesn't name an image", path.to_string_lossy()))?;
+        let texture = tm.load(&image)?;
+        return Ok(sheet.animation(texture));
+    }
+    let (surface, sheet) = Aseprite::load(path)?.sheet(None)?;
+    let name = path.to_string_lossy();
+    let texture = tm.add_surface(&name, &surface)?;
+    tm.record_modified(&name);
+    Ok(sheet.animation(texture))
+}
+
+/// draw a straight alpha pixel over another
+fn blend(dst: &mut [u8], src: &[u8], opacity: f32) {
+    let src_a = src[3] as f32 / 255.0 * opacity;
+    if src_a <= 0.0 {
+        return;
+    }
+    let dst_a = dst[3] as f32 / 255.0;
+    let out_a = src_a + dst_a * (1.0 - src_a);
+    for i in 0..3 {
+        let c = (src[i] as f32 * src_a + dst[i] as f32 * dst_a * (1.0 - src_a)) / out_a;
+        dst[i] = c.round() as u8;
+    }
+    dst[3] = (out_a * 255.0).round() as u8;
+}
+
+fn end_of_data() -> String {
+    String::from("unexpected end of aseprite data")
+}
+
+/// reads little endian values from the front of a slice
+struct Bytes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Bytes<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Bytes { data }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if count > self.data.len() {
+            return Err(end_of_data());
+        }
+        let (front, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(front)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.data)
+    }
+
+    fn skip(&mut self, count: usize) -> Result<(), String> {
+        self.take(count).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        Ok(self.u16()? as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).to_string())
+    }
+}
+
+#[cfg(test)]
+mod aseprite_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let sprite = Aseprite::load(Path::new("textures/hexagon.aseprite")).unwrap();
+        assert!(sprite.width == 15 && sprite.height == 15);
+        assert!(sprite.frame_count() == 2);
+        assert!(sprite.durations.iter().all(|d| (d - 0.3).abs() < 1e-9));
+        assert!(sprite.layers.len() == 2);
+        assert!(sprite.layers[0].name == "Layer 1" && !sprite.layers[0].visible);
+        assert!(sprite.layers[1].name == "Layer 2" && sprite.layers[1].visible);
+        assert!(sprite.tags.is_empty());
+
+        let pixels = sprite.frame_pixels(0, None);
+        assert!(pixels.len() == 15 * 15 * 4);
+        assert!(pixels.chunks(4).any(|p| p[3] > 0));
+
+        let (surface, sheet) = sprite.sheet(None).unwrap();
+        assert!(surface.size() == (30, 15));
+        assert!(sheet.frames.len() == 2);
+        assert!(sheet.frames[1].0.x == 15.0 && sheet.frames[1].0.y == 0.0);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Aseprite::parse(&[]).is_err());
+        let mut data = std::fs::read("textures/hexagon.aseprite").unwrap();
+        data[4] = 0;
+        assert!(Aseprite::parse(&data).is_err());
+        assert!(Aseprite::parse(&data[..200]).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+//! packs many images into a few large pages, so sprites that are drawn together share a texture
+
+use sdl2::surface::Surface;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::BlendMode;
+use sdl2::rect::Rect;
+use sdl2::image::{LoadSurface, SaveSurface};
+
+use std::collections::HashMap;
+use std::path::Path;
+
+pub const PAGE_SIZE : u32 = 1024;
+const PADDING : u32 = 1;
+
+/// where an image was placed in an atlas
+#[derive(Clone, Copy)]
+pub struct AtlasEntry {
+    pub page: usize,
+    pub rect: Rect,
+}
+
+/// images packed into pages, keyed by the path they were loaded from
+pub struct Atlas {
+    pub pages: Vec<Surface<'static>>,
+    pub entries: HashMap<String, AtlasEntry>,
+}
+
+impl Atlas {
+    /// read an atlas saved by `save`. A `page <image>` line starts a page, with the image path relative to
+    /// the manifest, then `<path> <x> <y> <width> <height>` lines place images on that page
+    pub fn load(manifest: &Path) -> Result<Atlas, String> {
+        let text = std::fs::read_to_string(manifest).map_err(|e| e.to_string())?;
+        let dir = manifest.parent().unwrap_or(Path::new(""));
+        let mut atlas = Atlas { pages: Vec::new(), entries: HashMap::new() };
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("invalid atlas entry on line {} of {}", i + 1, manifest.to_string_lossy());
+            if let Some(page) = line.strip_prefix("page ") {
+                atlas.pages.push(Surface::from_file(dir.join(page.trim()))?);
+                continue;
+            }
+            if atlas.pages.is_empty() {
+                return Err(invalid());
+            }
+            // split from the right so paths can contain spaces
+            let mut words = line.rsplitn(5, char::is_whitespace);
+            let mut num = || -> Result<i32, String> {
+                words.next().and_then(|w| w.parse().ok()).ok_or_else(invalid)
+            };
+            let (h, w, y, x) = (num()?, num()?, num()?, num()?);
+            let path = words.next().ok_or_else(invalid)?.trim();
+            atlas.entries.insert(path.to_string(), AtlasEntry {
+                page: atlas.pages.len() - 1,
+                rect: Rect::new(x, y, w as u32, h as u32),
+            });
+        }
+        Ok(atlas)
+    }
+
+    /// write the pages as pngs next to the manifest, so the atlas can be loaded without packing it again
+    pub fn save(&self, manifest: &Path) -> Result<(), String> {
+        let dir = manifest.parent().unwrap_or(Path::new(""));
+        let stem = manifest.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let mut text = String::from("# page <image> | <path> <x> <y> <width> <height>\n");
+        for (i, page) in self.pages.iter().enumerate() {
+            let name = format!("{}_{}.png", stem, i);
+            page.save(dir.join(&name))?;
+            text.push_str(&format!("page {}\n", name));
+            for (path, entry) in self.entries.iter().filter(|e| e.1.page == i) {
+                let r = entry.rect;
+                text.push_str(&format!("{} {} {} {} {}\n", path, r.x(), r.y(), r.width(), r.height()));
+            }
+        }
+        std::fs::write(manifest, text).map_err(|e| e.to_string())
+    }
+}
+
+/// collects images then packs them into pages
+pub struct AtlasBuilder {
+    images: Vec<(String, Surface<'static>)>,
+}
+
+impl Default for AtlasBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AtlasBuilder {
+    pub fn new() -> Self {
+        AtlasBuilder { images: Vec::new() }
+    }
+
+    /// an image that can't be loaded is left out, so loading its path from the `TextureManager` gives the fallback texture
+    pub fn add(&mut self, path: &Path) -> Result<(), String> {
+        let surface = match Surface::from_file(path) {
+            Ok(s) => s,
+            Err(e) => {
+                println!("failed to load {}: {}, leaving it out of the atlas", path.to_string_lossy(), e);
+                return Ok(());
+            },
+        };
+        if surface.width() + PADDING > PAGE_SIZE || surface.height() + PADDING > PAGE_SIZE {
+            return Err(format!("{} is too large for an atlas page", path.to_string_lossy()));
+        }
+        self.images.push((path.to_string_lossy().to_string(), surface));
+        Ok(())
+    }
+
+    /// pack the images in rows, tallest first so rows waste less space
+    pub fn build(mut self) -> Result<Atlas, String> {
+        self.images.sort_by_key(|i| std::cmp::Reverse(i.1.height()));
+        let mut atlas = Atlas { pages: Vec::new(), entries: HashMap::new() };
+        let (mut x, mut y, mut row_height) = (0, 0, 0);
+        for (path, mut image) in self.images.into_iter() {
+            let (w, h) = image.size();
+            if x + w + PADDING > PAGE_SIZE {
+                x = 0;
+                y += row_height + PADDING;
+                row_height = 0;
+            }
+            if atlas.pages.is_empty() || y + h + PADDING > PAGE_SIZE {
+                atlas.pages.push(Surface::new(PAGE_SIZE, PAGE_SIZE, PixelFormatEnum::RGBA8888)?);
+                x = 0;
+                y = 0;
+                row_height = 0;
+            }
+            let rect = Rect::new(x as i32, y as i32, w, h);
+            let page = atlas.pages.len() - 1;
+            // copy the alpha as it is instead of blending onto the empty page
+            image.set_blend_mode(BlendMode::None)?;
+            image.blit(None, &mut atlas.pages[page], rect)?;
+            atlas.entries.insert(path, AtlasEntry { page, rect });
+            x += w + PADDING;
+            row_height = row_height.max(h);
+        }
+        Ok(atlas)
+    }
+}
+
+#[cfg(test)]
+mod atlas_tests {
+    use super::*;
+
+    fn pixels(surface: &Surface) -> Vec<u8> {
+        let surface = surface.convert_format(PixelFormatEnum::ABGR8888).unwrap();
+        surface.with_lock(|p| p.to_vec())
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut builder = AtlasBuilder::new();
+        for path in ["textures/hl1.png", "textures/hl2.png", "textures/hexagon.png", "textures/missing.png"] {
+            builder.add(Path::new(path)).unwrap();
+        }
+        let atlas = builder.build().unwrap();
+        assert!(atlas.pages.len() == 1);
+        assert!(atlas.entries.len() == 3);
+        assert!(!atlas.entries.contains_key("textures/missing.png"));
+
+        let dir = std::env::temp_dir().join(format!("hex_atlas_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let manifest = dir.join("atlas.txt");
+        atlas.save(&manifest).unwrap();
+        let loaded = Atlas::load(&manifest);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert!(loaded.entries.len() == atlas.entries.len());
+        for (path, entry) in atlas.entries.iter() {
+            let e = loaded.entries[path];
+            assert!(e.page == entry.page && e.rect == entry.rect);
+            let image = Surface::from_file(path).unwrap();
+            assert!(e.rect.size() == image.size());
+        }
+        assert!(pixels(&loaded.pages[0]) == pixels(&atlas.pages[0]));
+    }
+}
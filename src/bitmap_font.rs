@@ -0,0 +1,278 @@
+//! pixel fonts loaded from an AngelCode BMFont `.fnt` text file and its page images,
+//! drawn at whole number scales so they stay sharp
+
+use sdl2::render::{Texture, TextureCreator, Canvas, RenderTarget};
+use sdl2::image::LoadTexture;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// where a character is in a page image and how to place it
+#[derive(Clone, Copy)]
+pub struct BitmapChar {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: i32,
+    pub y_offset: i32,
+    pub x_advance: i32,
+    pub page: usize,
+}
+
+/// the contents of a `.fnt` file
+pub struct FntData {
+    pub line_height: u32,
+    pub base: u32,
+    /// page image paths, relative to the `.fnt` file
+    pub pages: Vec<String>,
+    pub chars: HashMap<char, BitmapChar>,
+    pub kernings: HashMap<(char, char), i32>,
+}
+
+impl FntData {
+    /// parse the BMFont text format
+    pub fn parse(text: &str) -> Result<FntData, String> {
+        let mut fnt = FntData {
+            line_height: 0,
+            base: 0,
+            pages: Vec::new(),
+            chars: HashMap::new(),
+            kernings: HashMap::new(),
+        };
+        for line in text.lines() {
+            let mut words = line.split_whitespace();
+            let tag = match words.next() {
+                Some(t) => t,
+                None => continue,
+            };
+            let attribs = parse_attribs(line);
+            match tag {
+                "common" => {
+                    fnt.line_height = get(&attribs, "lineHeight")?;
+                    fnt.base = get(&attribs, "base")?;
+                },
+                "page" => {
+                    let id : usize = get(&attribs, "id")?;
+                    let file = match attribs.get("file") {
+                        Some(f) => f.to_string(),
+                        None => return Err(String::from("fnt page is missing file")),
+                    };
+                    if fnt.pages.len() <= id {
+                        fnt.pages.resize(id + 1, String::new());
+                    }
+                    fnt.pages[id] = file;
+                },
+                "char" => {
+                    let id : u32 = get(&attribs, "id")?;
+                    let c = match char::from_u32(id) {
+                        Some(c) => c,
+                        None => continue,
+                    };
+                    fnt.chars.insert(c, BitmapChar {
+                        x: get(&attribs, "x")?,
+                        y: get(&attribs, "y")?,
+                        width: get(&attribs, "width")?,
+                        height: get(&attribs, "height")?,
+                        x_offset: get(&attribs, "xoffset")?,
+                        y_offset: get(&attribs, "yoffset")?,
+                        x_advance: get(&attribs, "xadvance")?,
+                        page: get(&attribs, "page")?,
+                    });
+                },
+                "kerning" => {
+                    let first = char::from_u32(get(&attribs, "first")?);
+                    let second = char::from_u32(get(&attribs, "second")?);
+                    if let (Some(first), Some(second)) = (first, second) {
+                        fnt.kernings.insert((first, second), get(&attribs, "amount")?);
+                    }
+                },
+                _ => (),
+            }
+        }
+        if fnt.line_height == 0 {
+            return Err(String::from("fnt file is missing common lineHeight"));
+        }
+        Ok(fnt)
+    }
+}
+
+/// split `key=value` pairs, values may be quoted and contain spaces
+fn parse_attribs(line: &str) -> HashMap<&str, &str> {
+    let mut attribs = HashMap::new();
+    let mut rest = line;
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].split_whitespace().last().unwrap_or("");
+        rest = &rest[eq + 1..];
+        let value;
+        if rest.starts_with('"') {
+            let end = rest[1..].find('"').map(|e| e + 1).unwrap_or(rest.len());
+            value = &rest[1..end];
+            rest = &rest[(end + 1).min(rest.len())..];
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            value = &rest[..end];
+            rest = &rest[end..];
+        }
+        attribs.insert(key, value);
+    }
+    attribs
+}
+
+fn get<T: std::str::FromStr>(attribs: &HashMap<&str, &str>, key: &str) -> Result<T, String> {
+    match attribs.get(key) {
+        Some(v) => match v.parse() {
+            Ok(v) => Ok(v),
+            Err(_) => Err(format!("fnt attribute {} has invalid value {}", key, v)),
+        },
+        None => Err(format!("fnt attribute {} is missing", key)),
+    }
+}
+
+pub struct BitmapFont<'a> {
+    fnt: FntData,
+    pages: Vec<Texture<'a>>,
+}
+
+impl<'a> BitmapFont<'a> {
+    /// load a `.fnt` file and the page images it references
+    pub fn load<T>(path: &Path, texture_creator: &'a TextureCreator<T>) -> Result<BitmapFont<'a>, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let fnt = FntData::parse(&text)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut pages = Vec::new();
+        for p in fnt.pages.iter() {
+            pages.push(texture_creator.load_texture(dir.join(p))?);
+        }
+        Ok(BitmapFont { fnt, pages })
+    }
+
+    /// the whole number scale used to draw at `height` pixels, at least 1
+    pub fn scale(&self, height: f64) -> u32 {
+        ((height / self.fnt.line_height as f64).floor() as u32).max(1)
+    }
+
+    pub fn line_height(&self) -> u32 {
+        self.fnt.line_height
+    }
+
+    pub fn text_width(&self, text: &str, height: f64) -> f64 {
+        let mut width = 0;
+        let mut prev = None;
+        for c in text.chars() {
+            width += self.advance(prev, c);
+            prev = Some(c);
+        }
+        (width * self.scale(height) as i32) as f64
+    }
+
+    pub fn draw<R: RenderTarget>(&mut self, canvas: &mut Canvas<R>, text: &str, height: u32, x: i32, y: i32, colour: Color) -> Result<(), String> {
+        let scale = self.scale(height as f64) as i32;
+        for page in self.pages.iter_mut() {
+            page.set_color_mod(colour.r, colour.g, colour.b);
+            page.set_alpha_mod(colour.a);
+        }
+        let mut pen = x;
+        let mut prev = None;
+        for c in text.chars() {
+            if let Some(p) = prev {
+                pen += self.kerning(p, c) * scale;
+            }
+            prev = Some(c);
+            let ch = match self.fnt.chars.get(&c) {
+                Some(ch) => *ch,
+                None => continue,
+            };
+            if ch.width > 0 && ch.height > 0 && ch.page < self.pages.len() {
+                canvas.copy(
+                    &self.pages[ch.page],
+                    Rect::new(ch.x, ch.y, ch.width, ch.height),
+                    Rect::new(pen + ch.x_offset * scale, y + ch.y_offset * scale,
+                              ch.width * scale as u32, ch.height * scale as u32)
+                )?;
+            }
+            pen += ch.x_advance * scale;
+        }
+        Ok(())
+    }
+
+    fn kerning(&self, first: char, second: char) -> i32 {
+        *self.fnt.kernings.get(&(first, second)).unwrap_or(&0)
+    }
+
+    fn advance(&self, prev: Option<char>, c: char) -> i32 {
+        let kerning = match prev {
+            Some(p) => self.kerning(p, c),
+            None => 0,
+        };
+        kerning + self.fnt.chars.get(&c).map(|ch| ch.x_advance).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod bitmap_font_tests {
+    use super::*;
+
+    const FNT : &str = r#"info face="Pixel Font" size=8 bold=0 italic=0 charset="" unicode=1
+common lineHeight=8 base=7 scaleW=64 scaleH=64 pages=2 packed=0
+page id=0 file="pixel font_0.png"
+page id=1 file=pixel_1.png
+chars count=3
+char id=65   x=0     y=0     width=5     height=7     xoffset=0     yoffset=1     xadvance=6     page=0  chnl=15
+char id=86   x=6     y=0     width=5     height=7     xoffset=0     yoffset=1     xadvance=6     page=1  chnl=15
+char id=32   x=0     y=0     width=0     height=0     xoffset=0     yoffset=0     xadvance=3     page=0  chnl=15
+kernings count=1
+kerning first=65  second=86  amount=-1
+"#;
+
+    #[test]
+    fn test_parse_attribs() {
+        let attribs = parse_attribs(r#"info face="Pixel Font" size=8 charset="" padding=0,0,0,0"#);
+        assert!(attribs["face"] == "Pixel Font");
+        assert!(attribs["size"] == "8");
+        assert!(attribs["charset"].is_empty());
+        assert!(attribs["padding"] == "0,0,0,0");
+        assert!(!attribs.contains_key("info"));
+        // an unclosed quote runs to the end of the line
+        assert!(parse_attribs(r#"page id=0 file="a b"#)["file"] == "a b");
+        assert!(get::<u32>(&attribs, "size") == Ok(8));
+        assert!(get::<u32>(&attribs, "face").is_err());
+        assert!(get::<u32>(&attribs, "missing").is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        let fnt = FntData::parse(FNT).unwrap();
+        assert!(fnt.line_height == 8 && fnt.base == 7);
+        assert!(fnt.pages == vec!["pixel font_0.png", "pixel_1.png"]);
+        assert!(fnt.chars.len() == 3);
+        let v = fnt.chars[&'V'];
+        assert!(v.x == 6 && v.y == 0 && v.width == 5 && v.height == 7);
+        assert!(v.y_offset == 1 && v.x_advance == 6 && v.page == 1);
+        assert!(fnt.kernings.len() == 1 && fnt.kernings[&('A', 'V')] == -1);
+
+        assert!(FntData::parse("info face=x\n").is_err());
+        assert!(FntData::parse("common lineHeight=8\n").is_err());
+        assert!(FntData::parse(&FNT.replace("xadvance=6     page=1", "page=1")).is_err());
+        assert!(FntData::parse(&FNT.replace("file=pixel_1.png", "")).is_err());
+    }
+
+    #[test]
+    fn test_kerning_and_scale() {
+        let font = BitmapFont { fnt: FntData::parse(FNT).unwrap(), pages: Vec::new() };
+        assert!(font.line_height() == 8);
+        // only whole number scales, and never below 1
+        assert!(font.scale(4.0) == 1 && font.scale(8.0) == 1 && font.scale(15.9) == 1);
+        assert!(font.scale(16.0) == 2 && font.scale(25.0) == 3);
+
+        assert!(font.kerning('A', 'V') == -1 && font.kerning('V', 'A') == 0);
+        assert!(font.text_width("AV", 8.0) == 11.0);
+        assert!(font.text_width("VA", 8.0) == 12.0);
+        assert!(font.text_width("A V", 16.0) == 30.0);
+        // characters the font doesn't have take no space
+        assert!(font.text_width("AxV", 8.0) == 12.0);
+        assert!(font.text_width("", 8.0) == 0.0);
+    }
+}
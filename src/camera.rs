@@ -1,54 +1,302 @@
 use geometry::*;
 use crate::{TextureDraw, GameObject, TextDraw};
 use std::vec::Drain;
+use std::collections::HashSet;
+
+use rand::Rng;
+
+/// how much trauma is lost per second
+const TRAUMA_DECAY : f64 = 1.2;
+/// furthest the view is shaken at full trauma, in view units
+const MAX_SHAKE : f64 = 6.0;
+const DEFAULT_FOLLOW_SPEED : f64 = 6.0;
+
+/// the layers the game draws on, lower layers are drawn first
+pub mod layer {
+    pub const BACKGROUND : i32 = -100;
+    /// tiled map layers are drawn from here upwards in the order they are in the map
+    pub const MAP : i32 = 0;
+    pub const BOARD : i32 = 100;
+    pub const HIGHLIGHT : i32 = 200;
+    pub const EFFECTS : i32 = 300;
+}
+
+/// a draw waiting to be sorted
+struct QueuedDraw {
+    layer: i32,
+    depth: f64,
+    draw: TextureDraw,
+}
+
+/// how many draws the camera was given in a frame, and how many were dropped for being off screen
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CameraStats {
+    pub submitted: usize,
+    pub culled: usize,
+}
 
 pub struct Camera {
     rect: Rect,
+    /// the top left of the part of the window this camera draws to
+    window_pos: Vec2,
     window_size: Vec2,
     size_ratio: Vec2,
+    queue: Vec<QueuedDraw>,
     draws : Vec<TextureDraw>,
+    /// layers where objects lower on screen are drawn in front, instead of using their depth
+    y_sorted: HashSet<i32>,
+    zoom: f64,
+    zoom_target: f64,
+    /// the point in view space that zooming and rotation are centred on
+    focus: Vec2,
+    /// clockwise rotation of the view in degrees
+    angle: f64,
+    angle_target: f64,
+    /// the offset the camera is smoothly moving towards
+    target: Option<Vec2>,
+    follow_speed: f64,
+    bounds: Option<Rect>,
+    trauma: f64,
+    shake: Vec2,
+    frame_stats: CameraStats,
+    stats: CameraStats,
 }
 
 impl Camera {
     pub fn new(rect: Rect, window_size: Vec2) -> Camera {
         let mut cam = Camera {
             rect,
+            window_pos: Vec2::new(0.0, 0.0),
             window_size,
+            queue: Vec::new(),
             draws: Vec::new(),
+            y_sorted: HashSet::new(),
             size_ratio: Vec2::new(0.0, 0.0),
+            zoom: 1.0,
+            zoom_target: 1.0,
+            focus: Vec2::new(rect.w / 2.0, rect.h / 2.0),
+            angle: 0.0,
+            angle_target: 0.0,
+            target: None,
+            follow_speed: DEFAULT_FOLLOW_SPEED,
+            bounds: None,
+            trauma: 0.0,
+            shake: Vec2::new(0.0, 0.0),
+            frame_stats: CameraStats::default(),
+            stats: CameraStats::default(),
         };
         cam.update_size_ratio();
         cam
     }
     
 
+    /// the queued draws sorted by layer then depth, draws with the same key keep the order they were added in.
+    /// Runs of draws from the same atlas page are batched by sdl
     pub fn drain_draws(&mut self) -> Drain<TextureDraw> { 
+        self.queue.sort_by(|a, b| a.layer.cmp(&b.layer).then(a.depth.total_cmp(&b.depth)));
+        self.draws.clear();
+        self.draws.extend(self.queue.drain(..).map(|q| q.draw));
         self.draws.drain(..)
     }
+
+    /// sort a layer by the bottom edge of each object, so things further down the screen are drawn in front
+    pub fn set_y_sort(&mut self, layer: i32, y_sort: bool) {
+        if y_sort {
+            self.y_sorted.insert(layer);
+        } else {
+            self.y_sorted.remove(&layer);
+        }
+    }
     
+    /// queue an object to be drawn, moved by the offset and shake (scaled by its parallax)
+    /// then zoomed and rotated around the focus. Objects that end up outside the viewport are dropped
     pub fn add_cam_space(&mut self, game_obj: &GameObject) {
-        self.draws.push(
-            TextureDraw::new(
-                game_obj.texture,
-                Rect::new(
-                    ((game_obj.rect.x) - (self.rect.x * game_obj.parallax.x)) / self.size_ratio.x,
-                    ((game_obj.rect.y) - (self.rect.y * game_obj.parallax.y)) / self.size_ratio.y,
-                    game_obj.rect.w / self.size_ratio.x,
-                    game_obj.rect.h / self.size_ratio.y,
-                ),
-                game_obj.tex_rect,
-                game_obj.colour,
-            )
+        self.frame_stats.submitted += 1;
+        let origin = game_obj.origin.unwrap_or(Vec2::new(game_obj.rect.w / 2.0, game_obj.rect.h / 2.0));
+        let screen_origin = Vec2::new(
+            origin.x * self.zoom / self.size_ratio.x,
+            origin.y * self.zoom / self.size_ratio.y,
+        );
+        // the view rotates the object around its own origin, so move that origin to where the view puts it
+        let pos = self.world_to_screen(
+            Vec2::new(game_obj.rect.x + origin.x, game_obj.rect.y + origin.y),
+            game_obj.parallax,
+        );
+        let rect = Rect::new(
+            pos.x - screen_origin.x,
+            pos.y - screen_origin.y,
+            game_obj.rect.w * self.zoom / self.size_ratio.x,
+            game_obj.rect.h * self.zoom / self.size_ratio.y,
+        );
+        let angle = game_obj.angle + self.angle;
+        // a rotated object stays within its diagonal of the rotation origin
+        let reach = if angle != 0.0 || game_obj.flip.diagonal {
+            (rect.w * rect.w + rect.h * rect.h).sqrt()
+        } else {
+            0.0
+        };
+        let bounds = Rect::new(rect.x - reach, rect.y - reach, rect.w + reach * 2.0, rect.h + reach * 2.0);
+        if !bounds.colliding(&self.get_viewport()) {
+            self.frame_stats.culled += 1;
+            return;
+        }
+        let depth = if self.y_sorted.contains(&game_obj.layer) {
+            game_obj.rect.y + game_obj.rect.h
+        } else {
+            game_obj.depth
+        };
+        let mut draw = TextureDraw::new(
+            game_obj.texture,
+            rect,
+            game_obj.tex_rect,
+            game_obj.colour,
+        );
+        draw.angle = angle;
+        draw.origin = game_obj.origin.map(|_| screen_origin);
+        draw.flip = game_obj.flip;
+        self.queue.push(QueuedDraw {
+            layer: game_obj.layer,
+            depth,
+            draw,
+        });
+    }
+
+    /// where a point in the world appears in the window, for an object with the given parallax
+    pub fn world_to_screen(&self, pos: Vec2, parallax: Vec2) -> Vec2 {
+        let offset = self.parallax_offset(parallax);
+        let pos = rotate(Vec2::new(pos.x - offset.x - self.focus.x, pos.y - offset.y - self.focus.y), self.angle);
+        Vec2::new(
+            (pos.x * self.zoom + self.focus.x) / self.size_ratio.x + self.window_pos.x,
+            (pos.y * self.zoom + self.focus.y) / self.size_ratio.y + self.window_pos.y,
+        )
+    }
+
+    /// the point in the world under a window position, for an object with the given parallax
+    pub fn screen_to_world(&self, pos: Vec2, parallax: Vec2) -> Vec2 {
+        let offset = self.parallax_offset(parallax);
+        let pos = Vec2::new(
+            ((pos.x - self.window_pos.x) * self.size_ratio.x - self.focus.x) / self.zoom,
+            ((pos.y - self.window_pos.y) * self.size_ratio.y - self.focus.y) / self.zoom,
         );
+        let pos = rotate(pos, -self.angle);
+        Vec2::new(
+            pos.x + self.focus.x + offset.x,
+            pos.y + self.focus.y + offset.y,
+        )
+    }
+
+    /// the part of the world that is in the window, for objects with the given parallax.
+    /// While the view is rotated this is the smallest rect holding all of it
+    pub fn visible_rect(&self, parallax: Vec2) -> Rect {
+        let viewport = self.get_viewport();
+        let corners = [
+            Vec2::new(viewport.x, viewport.y),
+            Vec2::new(viewport.x + viewport.w, viewport.y),
+            Vec2::new(viewport.x, viewport.y + viewport.h),
+            Vec2::new(viewport.x + viewport.w, viewport.y + viewport.h),
+        ].map(|c| self.screen_to_world(c, parallax));
+        let min_x = corners.iter().map(|c| c.x).fold(f64::INFINITY, f64::min);
+        let min_y = corners.iter().map(|c| c.y).fold(f64::INFINITY, f64::min);
+        let max_x = corners.iter().map(|c| c.x).fold(f64::NEG_INFINITY, f64::max);
+        let max_y = corners.iter().map(|c| c.y).fold(f64::NEG_INFINITY, f64::max);
+        Rect::new(min_x, min_y, max_x - min_x, max_y - min_y)
     }
 
     pub fn get_offset(&self) -> Vec2 {
         return Vec2::new(self.rect.x, self.rect.y);
     }
 
+    /// move the camera straight to an offset, any follow target is kept
     pub fn set_offset(&mut self, offset: Vec2) {
         self.rect.x = offset.x;
         self.rect.y = offset.y;
+        self.clamp_to_bounds();
+    }
+
+    /// smoothly move the camera towards an offset, `None` stops following
+    pub fn follow(&mut self, target: Option<Vec2>) {
+        self.target = target;
+    }
+
+    /// how quickly the offset and zoom close the gap to their targets, higher is snappier
+    pub fn set_follow_speed(&mut self, speed: f64) {
+        self.follow_speed = speed;
+    }
+
+    /// keep the unzoomed view inside a region of the world, `None` lets it go anywhere
+    pub fn set_bounds(&mut self, bounds: Option<Rect>) {
+        self.bounds = bounds;
+        self.clamp_to_bounds();
+    }
+
+    pub fn get_zoom(&self) -> f64 {
+        self.zoom
+    }
+
+    /// zoom straight to `zoom` around `focus`, a point in view space
+    pub fn set_zoom(&mut self, zoom: f64, focus: Vec2) {
+        self.zoom = zoom;
+        self.zoom_target = zoom;
+        self.focus = focus;
+    }
+
+    /// smoothly zoom to `zoom` around `focus`, a point in view space
+    pub fn zoom_to(&mut self, zoom: f64, focus: Vec2) {
+        self.zoom_target = zoom;
+        self.focus = focus;
+    }
+
+    pub fn get_angle(&self) -> f64 {
+        self.angle
+    }
+
+    /// rotate the view straight to `angle` degrees clockwise around the focus
+    pub fn set_angle(&mut self, angle: f64) {
+        self.angle = angle;
+        self.angle_target = angle;
+    }
+
+    /// smoothly rotate the view to `angle` degrees clockwise around the focus
+    pub fn rotate_to(&mut self, angle: f64) {
+        self.angle_target = angle;
+    }
+
+    /// add screen shake, trauma is kept between 0 and 1 and the shake grows with its square
+    pub fn add_trauma(&mut self, amount: f64) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    pub fn get_trauma(&self) -> f64 {
+        self.trauma
+    }
+
+    /// the draw counts of the last finished frame
+    pub fn stats(&self) -> CameraStats {
+        self.stats
+    }
+
+    /// move towards the follow, zoom and angle targets and shake, call once a frame after drawing
+    pub fn update(&mut self, timer: f64) {
+        self.stats = self.frame_stats;
+        self.frame_stats = CameraStats::default();
+
+        let t = 1.0 - (-self.follow_speed * timer).exp();
+        if let Some(target) = self.target {
+            self.rect.x += (target.x - self.rect.x) * t;
+            self.rect.y += (target.y - self.rect.y) * t;
+            self.clamp_to_bounds();
+        }
+        self.zoom += (self.zoom_target - self.zoom) * t;
+        self.angle += (self.angle_target - self.angle) * t;
+
+        self.trauma = (self.trauma - TRAUMA_DECAY * timer).max(0.0);
+        let shake = self.trauma * self.trauma * MAX_SHAKE;
+        let mut rng = rand::thread_rng();
+        // whole view units so pixel art doesn't shimmer
+        self.shake = Vec2::new(
+            (rng.gen_range(-1.0..=1.0) * shake).round(),
+            (rng.gen_range(-1.0..=1.0) * shake).round(),
+        );
     }
 
     pub fn get_window_size(&self) -> Vec2 {
@@ -60,6 +308,18 @@ impl Camera {
         self.update_size_ratio();
     }
 
+    /// the part of the window the camera draws to, draws are clipped to it
+    pub fn get_viewport(&self) -> Rect {
+        Rect::new(self.window_pos.x, self.window_pos.y, self.window_size.x, self.window_size.y)
+    }
+
+    /// draw to part of the window, for split screen or picture in picture.
+    /// The view is stretched to fill it, so keep the aspect ratios the same to avoid distortion
+    pub fn set_viewport(&mut self, viewport: Rect) {
+        self.window_pos = Vec2::new(viewport.x, viewport.y);
+        self.set_window_size(Vec2::new(viewport.w, viewport.h));
+    }
+
     pub fn get_view_size(&self) -> Vec2 {
         Vec2::new(self.rect.w, self.rect.h)
     }
@@ -73,6 +333,20 @@ impl Camera {
         self.rect.w / self.rect.h
     }
 
+    fn parallax_offset(&self, parallax: Vec2) -> Vec2 {
+        Vec2::new(
+            (self.rect.x + self.shake.x) * parallax.x,
+            (self.rect.y + self.shake.y) * parallax.y,
+        )
+    }
+
+    fn clamp_to_bounds(&mut self) {
+        if let Some(b) = self.bounds {
+            self.rect.x = self.rect.x.min(b.x + b.w - self.rect.w).max(b.x);
+            self.rect.y = self.rect.y.min(b.y + b.h - self.rect.h).max(b.y);
+        }
+    }
+
     fn update_size_ratio(&mut self) {
         self.size_ratio = Vec2::new(
                 self.rect.w / self.window_size.x,
@@ -80,3 +354,128 @@ impl Camera {
         );
     }
 }
+
+/// rotate a point around the origin by `angle` degrees clockwise, y pointing down
+fn rotate(pos: Vec2, angle: f64) -> Vec2 {
+    if angle == 0.0 {
+        return pos;
+    }
+    let (sin, cos) = angle.to_radians().sin_cos();
+    Vec2::new(pos.x * cos - pos.y * sin, pos.x * sin + pos.y * cos)
+}
+
+#[cfg(test)]
+mod camera_tests {
+    use super::*;
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        (a.x - b.x).abs() < 1e-9 && (a.y - b.y).abs() < 1e-9
+    }
+
+    /// a 10x10 object, the texture id tells the draws apart
+    fn obj(id: usize, x: f64, y: f64) -> GameObject {
+        let mut obj = GameObject::new_from_tex(crate::resource::Texture { id, x: 0, y: 0, width: 10, height: 10 });
+        obj.rect.x = x;
+        obj.rect.y = y;
+        obj
+    }
+
+    #[test]
+    fn test_culling() {
+        let mut cam = Camera::new(Rect::new(0.0, 0.0, 100.0, 100.0), Vec2::new(200.0, 200.0));
+        cam.add_cam_space(&obj(0, 10.0, 10.0));
+        cam.add_cam_space(&obj(1, 500.0, 500.0));
+        cam.add_cam_space(&obj(2, -20.0, -20.0));
+        // partly on screen
+        cam.add_cam_space(&obj(3, -5.0, -5.0));
+        // only on screen once rotated
+        let mut rotated = obj(4, -12.0, 40.0);
+        cam.add_cam_space(&rotated);
+        rotated.angle = 45.0;
+        cam.add_cam_space(&rotated);
+        let ids: Vec<usize> = cam.drain_draws().map(|d| d.tex.id).collect();
+        assert!(ids == vec![0, 3, 4]);
+
+        // the counts are only reported once the frame is finished
+        assert!(cam.stats().submitted == 0);
+        cam.update(0.0);
+        let stats = cam.stats();
+        assert!(stats.submitted == 6);
+        assert!(stats.culled == 3);
+        cam.update(0.0);
+        assert!(cam.stats().submitted == 0 && cam.stats().culled == 0);
+    }
+
+    #[test]
+    fn test_rotation() {
+        let mut cam = Camera::new(Rect::new(0.0, 0.0, 100.0, 100.0), Vec2::new(200.0, 200.0));
+        let parallax = Vec2::new(1.0, 1.0);
+        cam.set_angle(90.0);
+        // the focus stays put and a point to its right ends up below it
+        assert!(close(cam.world_to_screen(Vec2::new(50.0, 50.0), parallax), Vec2::new(100.0, 100.0)));
+        assert!(close(cam.world_to_screen(Vec2::new(60.0, 50.0), parallax), Vec2::new(100.0, 120.0)));
+        cam.set_zoom(2.0, Vec2::new(20.0, 30.0));
+        cam.set_angle(30.0);
+        cam.set_offset(Vec2::new(5.0, -7.0));
+        let p = Vec2::new(12.0, 81.0);
+        assert!(close(cam.screen_to_world(cam.world_to_screen(p, parallax), parallax), p));
+    }
+
+    #[test]
+    fn test_draw_order() {
+        let mut cam = Camera::new(Rect::new(0.0, 0.0, 100.0, 100.0), Vec2::new(100.0, 100.0));
+        let queued = [
+            (layer::BOARD, 1.0),
+            (layer::MAP, 5.0),
+            (layer::BOARD, 0.0),
+            (layer::BOARD, 1.0),
+            (layer::MAP, 5.0),
+            (layer::BOARD, 1.0),
+        ];
+        for (id, (l, depth)) in queued.into_iter().enumerate() {
+            let mut o = obj(id, 10.0, 10.0);
+            o.layer = l;
+            o.depth = depth;
+            cam.add_cam_space(&o);
+        }
+        // draws with the same layer and depth keep the order they were added in
+        let ids: Vec<usize> = cam.drain_draws().map(|d| d.tex.id).collect();
+        assert!(ids == vec![1, 4, 2, 0, 3, 5]);
+        assert!(cam.drain_draws().count() == 0);
+
+        // y sorted layers ignore depth and use the bottom edge
+        cam.set_y_sort(layer::EFFECTS, true);
+        for (id, y, depth) in [(0, 50.0, 0.0), (1, 20.0, 9.0), (2, 50.0, -9.0), (3, 20.0, 0.0)] {
+            let mut o = obj(id, 10.0, y);
+            o.layer = layer::EFFECTS;
+            o.depth = depth;
+            cam.add_cam_space(&o);
+        }
+        let ids: Vec<usize> = cam.drain_draws().map(|d| d.tex.id).collect();
+        assert!(ids == vec![1, 3, 0, 2]);
+    }
+
+    #[test]
+    fn test_viewport() {
+        let mut cam = Camera::new(Rect::new(0.0, 0.0, 100.0, 100.0), Vec2::new(200.0, 200.0));
+        let parallax = Vec2::new(1.0, 1.0);
+        cam.set_viewport(Rect::new(50.0, 20.0, 100.0, 100.0));
+        assert!(cam.get_window_size().x == 100.0);
+        assert!(close(cam.world_to_screen(Vec2::new(0.0, 0.0), parallax), Vec2::new(50.0, 20.0)));
+        assert!(close(cam.world_to_screen(Vec2::new(100.0, 100.0), parallax), Vec2::new(150.0, 120.0)));
+        assert!(close(cam.screen_to_world(Vec2::new(50.0, 20.0), parallax), Vec2::new(0.0, 0.0)));
+
+        cam.set_offset(Vec2::new(10.0, 5.0));
+        let r = cam.visible_rect(parallax);
+        assert!(close(Vec2::new(r.x, r.y), Vec2::new(10.0, 5.0)));
+        assert!(close(Vec2::new(r.w, r.h), Vec2::new(100.0, 100.0)));
+        let r = cam.visible_rect(Vec2::new(0.5, 0.0));
+        assert!(close(Vec2::new(r.x, r.y), Vec2::new(5.0, 0.0)));
+
+        // culling is against the viewport, not the whole window
+        cam.add_cam_space(&obj(0, 10.0, 5.0));
+        cam.add_cam_space(&obj(1, -30.0, 0.0));
+        let ids: Vec<usize> = cam.drain_draws().map(|d| d.tex.id).collect();
+        assert!(ids == vec![0]);
+    }
+}
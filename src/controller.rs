@@ -0,0 +1,109 @@
+//! open game controllers as they are plugged in and give each one a player number
+
+use sdl2::controller::GameController;
+use sdl2::GameControllerSubsystem;
+use sdl2::event::Event;
+use crate::input::Input;
+
+/// controllers that are plugged in, indexed by player, unplugging leaves a gap that the next controller fills
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    players: Vec<Option<GameController>>,
+}
+
+impl Controllers {
+    pub fn new(subsystem: GameControllerSubsystem) -> Self {
+        Controllers {
+            subsystem,
+            players: Vec::new(),
+        }
+    }
+
+    /// open or close controllers on hotplug events, SDL also sends added events for controllers
+    /// that were already plugged in at startup
+    pub fn handle_event(&mut self, event: &Event) -> Result<(), String> {
+        match event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                let controller = self.subsystem.open(*which).map_err(|e| e.to_string())?;
+                if self.find(controller.instance_id()).is_some() {
+                    return Ok(());
+                }
+                println!("controller connected: {}", controller.name());
+                match self.players.iter().position(|p| p.is_none()) {
+                    Some(free) => self.players[free] = Some(controller),
+                    None => self.players.push(Some(controller)),
+                }
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                if let Some(player) = self.find(*which) {
+                    self.players[player] = None;
+                }
+            },
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// the player a controller event came from, `None` for other events.
+    /// Call it before `handle_event`, so a removed controller's player can still be found to release its input
+    pub fn player(&self, event: &Event) -> Option<usize> {
+        match event {
+            Event::ControllerButtonDown { which, .. } |
+            Event::ControllerButtonUp { which, .. } |
+            Event::ControllerAxisMotion { which, .. } |
+            Event::ControllerDeviceRemoved { which, .. } => self.find(*which),
+            _ => None,
+        }
+    }
+
+    pub fn connected(&self) -> usize {
+        self.players.iter().filter(|p| p.is_some()).count()
+    }
+
+    fn find(&self, instance_id: u32) -> Option<usize> {
+        self.players.iter().position(|p| match p {
+            Some(c) => c.instance_id() == instance_id,
+            None => false,
+        })
+    }
+}
+
+/// the input a player's events go to, events that aren't from a controller go to the first player.
+/// Adds inputs for players that don't have one yet
+pub fn player_input(inputs: &mut Vec<Input>, player: Option<usize>) -> &mut Input {
+    let player = player.unwrap_or(0);
+    while inputs.len() <= player {
+        inputs.push(Input::new());
+    }
+    &mut inputs[player]
+}
+
+#[cfg(test)]
+mod controller_tests {
+    use super::*;
+    use crate::input::{Action, Bindings};
+    use sdl2::controller::{Axis, Button};
+    use sdl2::keyboard::Scancode;
+
+    #[test]
+    fn test_player_input() {
+        let bindings = Bindings::new();
+        let mut inputs = vec![Input::new()];
+        let button = Event::ControllerButtonDown { timestamp: 0, which: 7, button: Button::A };
+        player_input(&mut inputs, Some(1)).handle_event(&button, &bindings);
+        assert!(inputs.len() == 2);
+        assert!(inputs[1].held(Action::A));
+        assert!(!inputs[0].held(Action::A));
+        let stick = Event::ControllerAxisMotion { timestamp: 0, which: 7, axis: Axis::LeftX, value: i16::MAX };
+        player_input(&mut inputs, Some(2)).handle_event(&stick, &bindings);
+        assert!(inputs[2].held(Action::Right));
+        assert!(!inputs[1].held(Action::Right));
+        let key = Event::KeyDown {
+            timestamp: 0, window_id: 0, keycode: None, scancode: Some(Scancode::Left),
+            keymod: sdl2::keyboard::Mod::NOMOD, repeat: false,
+        };
+        player_input(&mut inputs, None).handle_event(&key, &bindings);
+        assert!(inputs[0].held(Action::Left));
+        assert!(!inputs[1].held(Action::Left) && !inputs[2].held(Action::Left));
+    }
+}
@@ -0,0 +1,195 @@
+//! rasterizes each glyph once into shared atlas textures, so drawing text doesn't create a texture every frame
+
+use sdl2::render::{Texture, TextureCreator, Canvas, BlendMode, RenderTarget};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::ttf;
+
+use std::collections::HashMap;
+
+const PAGE_SIZE : u32 = 1024;
+const PADDING : i32 = 1;
+
+#[derive(Clone, Copy)]
+struct Glyph {
+    page: usize,
+    rect: Rect,
+}
+
+/// counts for checking how well the cache is being reused
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GlyphCacheStats {
+    /// glyphs stored in the atlas pages
+    pub glyphs: usize,
+    pub pages: usize,
+    /// glyph lookups that were already in the atlas
+    pub hits: u64,
+    /// glyph lookups that had to be rasterized
+    pub misses: u64,
+}
+
+/// a font loaded at a size for `GlyphCache::draw`
+#[derive(Clone, Copy)]
+pub struct GlyphFont<'f> {
+    /// the id of the font in `FontManager`, fonts with the same id share glyphs
+    pub id: usize,
+    /// the font loaded at a size close to `height`
+    pub font: &'f ttf::Font<'f, 'static>,
+    /// the line height in pixels, glyphs are scaled to it
+    pub height: u32,
+}
+
+/// glyphs are keyed by font id, line height in pixels and character.
+/// Characters the font can't render are stored as `None` and skipped
+pub struct GlyphCache<'a> {
+    pages: Vec<Texture<'a>>,
+    glyphs: HashMap<(usize, u32, char), Option<Glyph>>,
+    cursor_x: i32,
+    cursor_y: i32,
+    row_height: i32,
+    quads: Vec<(usize, Rect, Rect)>,
+    stats: GlyphCacheStats,
+}
+
+impl<'a> Default for GlyphCache<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> GlyphCache<'a> {
+    pub fn new() -> Self {
+        GlyphCache {
+            pages: Vec::new(),
+            glyphs: HashMap::new(),
+            cursor_x: 0,
+            cursor_y: 0,
+            row_height: 0,
+            quads: Vec::new(),
+            stats: GlyphCacheStats { glyphs: 0, pages: 0, hits: 0, misses: 0 },
+        }
+    }
+
+    pub fn stats(&self) -> GlyphCacheStats {
+        self.stats
+    }
+
+    /// the width in pixels `draw` would give the text, rasterizing any glyphs that aren't cached yet
+    pub fn width<T>(&mut self, texture_creator: &'a TextureCreator<T>, font: GlyphFont, text: &str) -> Result<u32, String> {
+        let mut width = 0;
+        for c in text.chars() {
+            if let Some(glyph) = self.glyph(texture_creator, font, c)? {
+                width += glyph_width(&glyph, font.height);
+            }
+        }
+        Ok(width)
+    }
+
+    /// draw a line of text with its top left at `x`, `y`. Once every glyph is cached this doesn't allocate
+    pub fn draw<T, R: RenderTarget>(&mut self, canvas: &mut Canvas<R>, texture_creator: &'a TextureCreator<T>,
+                   font: GlyphFont, text: &str, (x, y): (i32, i32), colour: Color) -> Result<(), String> {
+        self.quads.clear();
+        let mut x = x;
+        for c in text.chars() {
+            let glyph = match self.glyph(texture_creator, font, c)? {
+                Some(g) => g,
+                None => continue,
+            };
+            let width = glyph_width(&glyph, font.height);
+            self.quads.push((glyph.page, glyph.rect, Rect::new(x, y, width, font.height)));
+            x += width as i32;
+        }
+
+        // submit every glyph on the same page together
+        self.quads.sort_unstable_by_key(|q| q.0);
+        let mut current = usize::MAX;
+        for (page, src, dst) in self.quads.iter() {
+            if *page != current {
+                current = *page;
+                self.pages[current].set_color_mod(colour.r, colour.g, colour.b);
+                self.pages[current].set_alpha_mod(colour.a);
+            }
+            canvas.copy(&self.pages[current], *src, *dst)?;
+        }
+        Ok(())
+    }
+
+    /// a cached glyph, or `None` if the font can't render the character
+    fn glyph<T>(&mut self, texture_creator: &'a TextureCreator<T>, font: GlyphFont, c: char) -> Result<Option<Glyph>, String> {
+        let key = (font.id, font.height, c);
+        if let Some(g) = self.glyphs.get(&key) {
+            self.stats.hits += 1;
+            return Ok(*g);
+        }
+        self.stats.misses += 1;
+        let glyph = self.rasterize(texture_creator, font.font, c)?;
+        if glyph.is_some() {
+            self.stats.glyphs += 1;
+        }
+        self.glyphs.insert(key, glyph);
+        Ok(glyph)
+    }
+
+    /// only failing to make a page is an error, a character that can't be rendered is logged and skipped
+    fn rasterize<T>(&mut self, texture_creator: &'a TextureCreator<T>, font: &ttf::Font, c: char) -> Result<Option<Glyph>, String> {
+        // control characters draw nothing, and sdl2 panics on a nul
+        if c.is_control() {
+            return Ok(None);
+        }
+        let surface = font.render_char(c)
+                          .blended(Color::RGB(255, 255, 255))
+                          .map_err(|e| e.to_string())
+                          .and_then(|s| s.convert_format(PixelFormatEnum::ARGB8888));
+        let surface = match surface {
+            Ok(s) => s,
+            Err(e) => {
+                println!("failed to render glyph {:?}: {}, skipping it", c, e);
+                return Ok(None);
+            },
+        };
+        let w = surface.width() as i32;
+        let h = surface.height() as i32;
+        if w == 0 || h == 0 {
+            return Ok(None);
+        }
+        if w + PADDING > PAGE_SIZE as i32 || h + PADDING > PAGE_SIZE as i32 {
+            println!("glyph {:?} is too large for a glyph cache page, skipping it", c);
+            return Ok(None);
+        }
+
+        if self.cursor_x + w + PADDING > PAGE_SIZE as i32 {
+            self.cursor_x = 0;
+            self.cursor_y += self.row_height + PADDING;
+            self.row_height = 0;
+        }
+        if self.pages.is_empty() || self.cursor_y + h + PADDING > PAGE_SIZE as i32 {
+            let mut page = texture_creator
+                .create_texture_static(PixelFormatEnum::ARGB8888, PAGE_SIZE, PAGE_SIZE)
+                .map_err(|e| e.to_string())?;
+            page.set_blend_mode(BlendMode::Blend);
+            self.pages.push(page);
+            self.stats.pages += 1;
+            self.cursor_x = 0;
+            self.cursor_y = 0;
+            self.row_height = 0;
+        }
+
+        let glyph = Glyph {
+            page: self.pages.len() - 1,
+            rect: Rect::new(self.cursor_x, self.cursor_y, w as u32, h as u32),
+        };
+        let pitch = surface.pitch() as usize;
+        let page = &mut self.pages[glyph.page];
+        surface.with_lock(|pixels| page.update(glyph.rect, pixels, pitch))
+               .map_err(|e| e.to_string())?;
+
+        self.cursor_x += w + PADDING;
+        self.row_height = self.row_height.max(h);
+        Ok(Some(glyph))
+    }
+}
+
+/// the width of a glyph scaled to `height`, rounded down like it is drawn
+fn glyph_width(glyph: &Glyph, height: u32) -> u32 {
+    (glyph.rect.width() as f64 * height as f64 / glyph.rect.height() as f64) as u32
+}
@@ -1,6 +1,6 @@
 use crate::Colour;
 use crate::resource::Texture;
-use crate::{GameObject, camera::Camera, TextureManager, resource, input::Input};
+use crate::{GameObject, animation::{Animation, Clip, Frame, PlayMode}, camera::{Camera, layer}, TextureManager, resource, input::{self, Input, Action, AutoRepeat}};
 use geometry::*;
 use std::path::Path;
 use std::f64::consts;
@@ -24,6 +24,10 @@ const BOARD_CENTER: Vec2 = Vec2{x: 120.0, y: 80.0};
 const BOARD_RADIUS : usize = 6;
 const BOARD_SIZE : usize = board_size(BOARD_RADIUS);
 const HL_SWAP : f64 = 0.4;
+/// how far the mouse is dragged to shift a ring one step
+const DRAG_STEP : f64 = HEX.x;
+const HOVER_ALPHA : u8 = 110;
+const REPEAT_ACTIONS : [Action; 4] = [Action::Up, Action::Down, Action::Left, Action::Right];
 
 const INITIAL_FALL_DELAY : f64 = 2.5;
 const INITIAL_SPAWN_DELAY : f64 = 12.0;
@@ -82,16 +86,19 @@ struct Hex {
     pub rating: i32,
     pub to_kill: bool,
     pub counted: bool,
+    /// shown as the white hex that ended the game
+    pub lost: bool,
 }
 
 impl Hex {
     pub fn blank() -> Hex {
         Hex {
-            obj: GameObject::new_from_tex(resource::Texture { id: 0, width: 0, height: 0}),
+            obj: GameObject::new_from_tex(resource::Texture { id: 0, x: 0, y: 0, width: 0, height: 0}),
             tile: Tile::Blank,
             rating: 0,
             to_kill: false,
             counted: false,
+            lost: false,
         }
     }
     pub fn new(obj: GameObject) -> Hex {
@@ -101,6 +108,7 @@ impl Hex {
             rating: 0,
             to_kill: false,
             counted: false,
+            lost: false,
         }
     }
 }
@@ -114,6 +122,78 @@ enum Tile {
     Blank,
 }
 
+/// alternate tile colours for colour blind players
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum Palette {
+    Default,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+}
+
+impl Palette {
+    /// the palette after this one, wrapping back to `Default`
+    pub fn next(&self) -> Palette {
+        match self {
+            Palette::Default => Palette::Deuteranopia,
+            Palette::Deuteranopia => Palette::Protanopia,
+            Palette::Protanopia => Palette::Tritanopia,
+            Palette::Tritanopia => Palette::Default,
+        }
+    }
+
+    /// the tint used for a tile, `None` means the tile's own texture is drawn
+    fn colour(&self, tile: Tile) -> Option<Colour> {
+        let c = match self {
+            Palette::Default => return None,
+            Palette::Deuteranopia => match tile {
+                Tile::Red => (213, 94, 0),
+                Tile::Green => (86, 180, 233),
+                Tile::Blue => (0, 70, 160),
+                Tile::Yellow => (240, 228, 66),
+                Tile::Blank => return None,
+            },
+            Palette::Protanopia => match tile {
+                Tile::Red => (230, 159, 0),
+                Tile::Green => (86, 180, 233),
+                Tile::Blue => (0, 70, 160),
+                Tile::Yellow => (245, 245, 200),
+                Tile::Blank => return None,
+            },
+            Palette::Tritanopia => match tile {
+                Tile::Red => (220, 50, 32),
+                Tile::Green => (0, 158, 115),
+                Tile::Blue => (120, 120, 120),
+                Tile::Yellow => (255, 190, 220),
+                Tile::Blank => return None,
+            },
+        };
+        Some(Colour::new(c.0, c.1, c.2, 255))
+    }
+}
+
+/// how tiles are told apart, can be changed while playing
+#[derive(Clone, Copy)]
+pub struct TileStyle {
+    pub palette: Palette,
+    pub symbols: bool,
+}
+
+impl Default for TileStyle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TileStyle {
+    pub fn new() -> TileStyle {
+        TileStyle {
+            palette: Palette::Default,
+            symbols: false,
+        }
+    }
+}
+
 fn random_tile() -> Tile {
     let x: usize = rand::thread_rng().gen_range(0..4);
     match x {
@@ -128,11 +208,16 @@ fn random_tile() -> Tile {
 pub struct HexGrid {
     grid : [Hex; BOARD_SIZE],
     tiles: HashMap<Tile, Texture>,
-    hl: [GameObject; 2],
+    symbols: HashMap<Tile, Texture>,
+    style: TileStyle,
+    hl: GameObject,
+    hl_anim: Animation,
     hl_y: usize,
-    hl_timer: f64,
-    hl_active: usize,
-    prev_input: Input,
+    hover_y: Option<usize>,
+    drag_start: Option<f64>,
+    drag_steps: i32,
+    /// held direction repeats, in the order of `REPEAT_ACTIONS`
+    repeats: [AutoRepeat; 4],
 
     drop_delay: f64,
     drop_timer: f64,
@@ -141,6 +226,8 @@ pub struct HexGrid {
     spawn_delay: f64,
 
     score: usize,
+    /// tiles cleared by the last update
+    cleared: usize,
     black_hex: Texture,
     white_hex: Texture,
     lost: bool,
@@ -154,8 +241,15 @@ impl HexGrid {
         tiles.insert(Tile::Green, tm.load(Path::new("textures/tile/green.png"))?);
         tiles.insert(Tile::Blue, tm.load(Path::new("textures/tile/blue.png"))?);
         tiles.insert(Tile::Yellow, tm.load(Path::new("textures/tile/yellow.png"))?);
+
+        let mut symbols = HashMap::<Tile, Texture>::new();
+        symbols.insert(Tile::Red, tm.load(Path::new("textures/tile/symbol/circle.png"))?);
+        symbols.insert(Tile::Green, tm.load(Path::new("textures/tile/symbol/triangle.png"))?);
+        symbols.insert(Tile::Blue, tm.load(Path::new("textures/tile/symbol/square.png"))?);
+        symbols.insert(Tile::Yellow, tm.load(Path::new("textures/tile/symbol/cross.png"))?);
         
-        let obj = GameObject::new_from_tex(tm.load(Path::new("textures/tile/blue.png"))?);
+        let mut obj = GameObject::new_from_tex(tm.load(Path::new("textures/tile/blue.png"))?);
+        obj.layer = layer::BOARD;
         let black_hex = tm.load(Path::new("textures/tile/mid.png"))?;
         let white_hex = tm.load(Path::new("textures/tile/white.png"))?;
         let mut grid = [Hex::blank();BOARD_SIZE];
@@ -185,20 +279,32 @@ impl HexGrid {
             x_total += x_size;
         }
 
+        let mut hl = GameObject::new_from_tex(tm.load(Path::new("textures/hl1.png"))?);
+        hl.layer = layer::HIGHLIGHT;
+        let mut hl_anim = Animation::new();
+        hl_anim.add_clip("blink", Clip::new(vec![
+            Frame::new(tm.load(Path::new("textures/hl1.png"))?, HL_SWAP),
+            Frame::new(tm.load(Path::new("textures/hl2.png"))?, HL_SWAP),
+        ], PlayMode::Loop));
+
         Ok(HexGrid {
             grid,
             tiles,
-            hl: [GameObject::new_from_tex(tm.load(Path::new("textures/hl1.png"))?),
-                 GameObject::new_from_tex(tm.load(Path::new("textures/hl2.png"))?)],
+            symbols,
+            style: TileStyle::new(),
+            hl,
+            hl_anim,
             hl_y: 1,
-            hl_timer: 0.0,
-            hl_active: 0,
-            prev_input: Input::new(),
+            hover_y: None,
+            drag_start: None,
+            drag_steps: 0,
+            repeats: [AutoRepeat::new(input::DEFAULT_REPEAT_DELAY, input::DEFAULT_REPEAT_RATE); 4],
             drop_delay: INITIAL_FALL_DELAY,
             drop_timer: 0.0,
             spawn_timer: INITIAL_SPAWN_DELAY / 2.0,
             spawn_delay: INITIAL_SPAWN_DELAY,
             score: 0,
+            cleared: 0,
             black_hex,
             white_hex,
             lost: false,
@@ -207,14 +313,58 @@ impl HexGrid {
 
     pub fn draw(&self, cam: &mut Camera) {
         for g in 0..BOARD_SIZE {
-            cam.add_cam_space(&self.grid[g].obj);
+            let hex = &self.grid[g];
+            // only restyle hexes showing their tile, so the lost marker stays visible
+            if hex.tile == Tile::Blank || hex.lost {
+                cam.add_cam_space(&hex.obj);
+                continue;
+            }
+            let mut obj = hex.obj;
+            if let Some(colour) = self.style.palette.colour(hex.tile) {
+                obj.texture = self.white_hex;
+                obj.colour = colour;
+            }
+            cam.add_cam_space(&obj);
+            if self.style.symbols {
+                obj.texture = self.symbols[&hex.tile];
+                obj.colour = Colour::white();
+                obj.depth = 1.0;
+                cam.add_cam_space(&obj);
+            }
+        }
+        if let Some(hover_y) = self.hover_y {
+            if hover_y != self.hl_y {
+                let mut hover = self.hl;
+                hover.colour.a = HOVER_ALPHA;
+                for x in 0..get_y_size(hover_y) {
+                    hover.rect = self.grid[Self::get_index(x, hover_y)].obj.rect;
+                    cam.add_cam_space(&hover);
+                }
+            }
         }
-        let mut active = self.hl[self.hl_active];
+        let mut active = self.hl_anim.object(&self.hl);
         for x in 0..self.y_ring() {
             active.rect = self.grid[Self::get_index(x, self.hl_y)].obj.rect;
             cam.add_cam_space(&active);
         }
     }
+
+    /// the hex cell under a point in board space, as (x, ring)
+    fn cell_at(&self, pos: Vec2) -> Option<(usize, usize)> {
+        let mut closest = None;
+        let mut closest_dist = HEX.w / 2.0;
+        for y in 0..BOARD_RADIUS {
+            for x in 0..get_y_size(y) {
+                let c = self.grid[Self::get_index(x, y)].obj.rect.centre();
+                let dist = ((c.x - pos.x).powi(2) + (c.y - pos.y).powi(2)).sqrt();
+                if dist < closest_dist {
+                    closest_dist = dist;
+                    closest = Some((x, y));
+                }
+            }
+        }
+        closest
+    }
     
     fn get_index(mut x: usize, y: usize) -> usize {
         let off = if y == 0 {
@@ -226,14 +376,11 @@ impl HexGrid {
         off + x
     }
 
-    pub fn update(&mut self, timer: &f64, input: &Input) {
-        self.input_handle(input);
+    pub fn update(&mut self, timer: &f64, input: &Input, cam: &Camera) {
+        self.mouse_handle(input, cam);
+        self.input_handle(input, *timer);
         self.game_logic(*timer);
-        self.hl_timer += timer;
-        if self.hl_timer > HL_SWAP {
-            self.hl_timer = 0.0;
-            self.hl_active = (self.hl_active + 1) % 2;
-        }
+        self.hl_anim.update(*timer);
     }
 
     fn y_ring(&self) -> usize {
@@ -248,6 +395,7 @@ impl HexGrid {
         let i = Self::get_index(x, y);
         self.grid[i].tile = tile;
         self.grid[i].obj.texture = self.tiles[&tile];
+        self.grid[i].lost = false;
     }
 
     fn ring_shift(&mut self, dir: i32) {
@@ -294,41 +442,84 @@ impl HexGrid {
         moved
     }
     
-    fn input_handle(&mut self, input: &Input) {
-        if input.up && !self.prev_input.up {
+    /// hovering shows a ring, clicking selects it, and dragging sideways shifts it a step every `DRAG_STEP`
+    fn mouse_handle(&mut self, input: &Input, cam: &Camera) {
+        let pos = cam.screen_to_world(
+            Vec2::new(input.mouse.x as f64, input.mouse.y as f64),
+            Vec2::new(1.0, 1.0),
+        );
+        self.hover_y = match self.cell_at(pos) {
+            Some((_, y)) if y != 0 => Some(y),
+            _ => None,
+        };
+
+        if input.mouse.left_pressed {
+            if let Some(y) = self.hover_y {
+                self.hl_y = y;
+                self.drag_start = Some(pos.x);
+                self.drag_steps = 0;
+            }
+        }
+        if !input.mouse.left_click {
+            self.drag_start = None;
+        }
+        if let Some(start) = self.drag_start {
+            let steps = ((pos.x - start) / DRAG_STEP).trunc() as i32;
+            while self.drag_steps != steps {
+                let dir = (steps - self.drag_steps).signum();
+                self.ring_shift(dir);
+                self.drag_steps += dir;
+            }
+        }
+    }
+
+    /// set how long a direction is held before it repeats, and the time between repeats
+    pub fn set_auto_repeat(&mut self, delay: f64, rate: f64) {
+        for repeat in self.repeats.iter_mut() {
+            repeat.delay = delay;
+            repeat.rate = rate;
+        }
+    }
+
+    fn input_handle(&mut self, input: &Input, timer: f64) {
+        let mut counts = [0; 4];
+        for (i, action) in REPEAT_ACTIONS.iter().enumerate() {
+            counts[i] = self.repeats[i].update(input, *action, timer);
+        }
+        let [up, down, left, right] = counts;
+        for _ in 0..up {
             self.hl_y = (self.hl_y + 1) % BOARD_RADIUS;
             if self.hl_y == 0 {
                 self.hl_y += 1;
             }
         }
-        if input.down && !self.prev_input.down {
+        for _ in 0..down {
             self.hl_y = ((self.hl_y + BOARD_RADIUS) - 1) % BOARD_RADIUS;
             if self.hl_y == 0 {
                 self.hl_y = BOARD_RADIUS - 1;
             }
         }
 
-        if input.right && !self.prev_input.right {
+        for _ in 0..right {
             self.ring_shift(1);
         }
         
-        if input.left && !self.prev_input.left {
+        for _ in 0..left {
             self.ring_shift(-1);
         }
 
-        if input.a && !self.prev_input.a {
+        if input.pressed(Action::A) {
             //self.move_ring(self.hl_y, true);
             self.drop_timer = self.drop_delay;
         }
-         if input.b && !self.prev_input.b {
+         if input.pressed(Action::B) {
              //self.move_ring(self.hl_y, false);
          }
 
-         if input.debug_1 && !self.prev_input.debug_1 {
+         if input.pressed(Action::Debug1) {
              self.score += 10;
         }
 
-        self.prev_input = *input;
     }
 
     fn spawn_ring(&mut self) {
@@ -336,7 +527,9 @@ impl HexGrid {
         for x in 0..6 {
             if self.get_tile(x, 1) != Tile::Blank {
                 self.lost = true;
-                self.grid[Self::get_index(x, 1)].obj.texture = self.white_hex;
+                let i = Self::get_index(x, 1);
+                self.grid[i].obj.texture = self.white_hex;
+                self.grid[i].lost = true;
             }  else {
                 let mut tile = random_tile();
                 if tile == prev {
@@ -426,6 +619,7 @@ impl HexGrid {
             self.grid[i].obj.texture = self.black_hex;
             self.grid[i].to_kill = false;
             self.score += 1;
+            self.cleared += 1;
         }
     }
         
@@ -442,6 +636,7 @@ impl HexGrid {
     }
 
     fn clear_lines(&mut self) {
+        self.cleared = 0;
         self.iter_grid(|s: &mut Self, x: usize, y: usize, _:Tile| {
             let i = Self::get_index(x, y);
             s.grid[i].counted = false;
@@ -484,19 +679,114 @@ impl HexGrid {
         self.score
     }
 
+    /// how many tiles were cleared by the last update
+    pub fn cleared(&self) -> usize {
+        self.cleared
+    }
+
     pub fn reset(&mut self) {
         self.spawn_timer = INITIAL_SPAWN_DELAY / 2.0;
         self.spawn_delay = INITIAL_SPAWN_DELAY;
         self.drop_delay = INITIAL_FALL_DELAY;
         self.score = 0;
+        self.cleared = 0;
         self.lost = false;
         self.hl_y = 1;
+        self.drag_start = None;
         self.iter_grid(|s: &mut Self, x: usize, y: usize, _: Tile| {
             s.change_tile(x, y, Tile::Blank);
         });
     }
 
+    pub fn tile_style(&self) -> TileStyle {
+        self.style
+    }
+
+    pub fn set_tile_style(&mut self, style: TileStyle) {
+        self.style = style;
+    }
+
     pub fn spawn_ratio(&self) -> f64 {
         self.drop_delay as f64/ self.drop_timer as f64
     }
 }
+
+#[cfg(test)]
+mod hex_tests {
+    use super::*;
+    use crate::render::Headless;
+    use sdl2::event::Event;
+    use sdl2::mouse::{MouseButton, MouseState};
+
+    fn camera() -> Camera {
+        Camera::new(Rect::new(0.0, 0.0, 240.0, 160.0), Vec2::new(240.0, 160.0))
+    }
+
+    fn ring(grid: &HexGrid, y: usize) -> Vec<Tile> {
+        (0..get_y_size(y)).map(|x| grid.get_tile(x, y)).collect()
+    }
+
+    #[test]
+    fn test_cell_at() {
+        let headless = Headless::new(8, 8).unwrap();
+        let texture_creator = headless.texture_creator();
+        let mut tm = TextureManager::new(&texture_creator);
+        let grid = HexGrid::new(&mut tm).unwrap();
+        for y in 0..BOARD_RADIUS {
+            for x in 0..get_y_size(y) {
+                let rect = grid.grid[HexGrid::get_index(x, y)].obj.rect;
+                let c = rect.centre();
+                assert!(grid.cell_at(c) == Some((x, y)));
+                assert!(grid.cell_at(Vec2::new(c.x + rect.w / 5.0, c.y - rect.h / 5.0)) == Some((x, y)));
+            }
+        }
+        assert!(grid.cell_at(Vec2::new(-100.0, -100.0)).is_none());
+    }
+
+    #[test]
+    fn test_drag() {
+        let headless = Headless::new(8, 8).unwrap();
+        let texture_creator = headless.texture_creator();
+        let mut tm = TextureManager::new(&texture_creator);
+        let mut grid = HexGrid::new(&mut tm).unwrap();
+        let cam = camera();
+        let bindings = input::Bindings::new();
+        let start = grid.grid[HexGrid::get_index(0, 2)].obj.rect.centre();
+        let before = ring(&grid, 2);
+
+        // a click with no motion before it picks the cell under the click
+        let mut input = Input::new();
+        input.handle_event(&Event::MouseButtonDown {
+            timestamp: 0, window_id: 0, which: 0, mouse_btn: MouseButton::Left, clicks: 1,
+            x: start.x as i32, y: start.y as i32,
+        }, &bindings);
+        grid.mouse_handle(&input, &cam);
+        assert!(grid.hl_y == 2 && grid.drag_steps == 0);
+
+        // dragging two and a half steps right shifts the ring twice
+        input.advance(0.1);
+        input.handle_event(&Event::MouseMotion {
+            timestamp: 0, window_id: 0, which: 0, mousestate: MouseState::from_sdl_state(1),
+            x: (start.x + DRAG_STEP * 2.5) as i32, y: start.y as i32, xrel: 0, yrel: 0,
+        }, &bindings);
+        grid.mouse_handle(&input, &cam);
+        assert!(grid.drag_steps == 2);
+        let mut shifted = HexGrid::new(&mut tm).unwrap();
+        for (x, tile) in before.iter().enumerate() {
+            shifted.change_tile(x, 2, *tile);
+        }
+        shifted.ring_shift(1);
+        shifted.ring_shift(1);
+        assert!(ring(&grid, 2) == ring(&shifted, 2));
+
+        // letting go ends the drag where it was, wherever the button is released
+        input.advance(0.1);
+        input.handle_event(&Event::MouseButtonUp {
+            timestamp: 0, window_id: 0, which: 0, mouse_btn: MouseButton::Left, clicks: 1,
+            x: (start.x - DRAG_STEP * 1.5) as i32, y: start.y as i32,
+        }, &bindings);
+        grid.mouse_handle(&input, &cam);
+        assert!(grid.drag_start.is_none());
+        assert!(ring(&grid, 2) == ring(&shifted, 2));
+    }
+}
@@ -0,0 +1,122 @@
+//! the best scores and the names of the players who got them, saved to a text file
+
+use std::path::Path;
+
+pub const TABLE_SIZE : usize = 5;
+pub const NAME_LENGTH : usize = 10;
+
+pub struct HighScores {
+    entries: Vec<(String, usize)>,
+}
+
+impl Default for HighScores {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HighScores {
+    pub fn new() -> Self {
+        HighScores { entries: Vec::new() }
+    }
+
+    /// read a table with one `score name` entry per line, invalid lines are skipped
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut table = HighScores::new();
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (score, name) = line.split_once(' ').unwrap_or((line, ""));
+            match score.trim().parse() {
+                Ok(score) => table.insert(name, score),
+                Err(_) => println!("skipping invalid high score entry on line {} of {}: {}", i + 1, path.to_string_lossy(), line),
+            }
+        }
+        Ok(table)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut text = String::new();
+        for (name, score) in self.entries.iter() {
+            text.push_str(&format!("{} {}\n", score, name));
+        }
+        std::fs::write(path, text).map_err(|e| e.to_string())
+    }
+
+    /// whether a score would get a place in the table
+    pub fn qualifies(&self, score: usize) -> bool {
+        score > 0 && (self.entries.len() < TABLE_SIZE || score > self.entries[self.entries.len() - 1].1)
+    }
+
+    pub fn insert(&mut self, name: &str, score: usize) {
+        let name : String = name.trim().chars().take(NAME_LENGTH).collect();
+        let place = self.entries.iter().position(|e| score > e.1).unwrap_or(self.entries.len());
+        self.entries.insert(place, (name, score));
+        self.entries.truncate(TABLE_SIZE);
+    }
+
+    /// entries from highest to lowest score
+    pub fn entries(&self) -> &[(String, usize)] {
+        &self.entries
+    }
+
+    pub fn best(&self) -> usize {
+        self.entries.first().map(|e| e.1).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod highscore_tests {
+    use super::*;
+    use crate::temp_path;
+
+    #[test]
+    fn test_insert() {
+        let mut table = HighScores::new();
+        for (i, score) in [30, 10, 50, 20, 40, 5].iter().enumerate() {
+            table.insert(&format!("p{}", i), *score);
+        }
+        let scores: Vec<usize> = table.entries().iter().map(|e| e.1).collect();
+        assert!(scores == vec![50, 40, 30, 20, 10]);
+        assert!(table.best() == 50);
+        assert!(table.entries()[0].0 == "p2");
+
+        table.insert("  a very long name indeed ", 60);
+        assert!(table.entries()[0].0 == "a very lon");
+    }
+
+    #[test]
+    fn test_qualifies() {
+        let mut table = HighScores::new();
+        assert!(!table.qualifies(0));
+        assert!(table.qualifies(1));
+        for score in 1..=TABLE_SIZE {
+            table.insert("p", score * 10);
+        }
+        assert!(!table.qualifies(10));
+        assert!(table.qualifies(11));
+    }
+
+    #[test]
+    fn test_save_load() {
+        let path = temp_path("highscore_round_trip.txt");
+        let mut table = HighScores::new();
+        table.insert("ann", 120);
+        table.insert("bo b", 80);
+        table.save(&path).unwrap();
+        let loaded = HighScores::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.entries() == table.entries());
+    }
+
+    #[test]
+    fn test_load_skips_invalid_lines() {
+        let path = temp_path("highscore_invalid.txt");
+        std::fs::write(&path, "100 ann\nnot a score\n\n50 bob\n").unwrap();
+        let loaded = HighScores::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.entries() == [(String::from("ann"), 100), (String::from("bob"), 50)]);
+    }
+}
@@ -1,16 +1,22 @@
 //! take sdl2 events and update a struct of bools for required controls
 
 use sdl2::event::Event;
-use sdl2::keyboard::Scancode;
+use sdl2::keyboard::{Scancode, Keycode};
 use sdl2::mouse::MouseButton;
+use sdl2::controller::{Button, Axis};
 
-/// Holds mouse input info
+use std::path::Path;
+
+/// Holds mouse input info, the pressed and moved flags only last for the frame they happened in
 #[derive(Copy, Clone)]
 pub struct Mouse {
     pub x : i32,
     pub y : i32,
     pub left_click : bool,
     pub right_click : bool,
+    pub left_pressed : bool,
+    pub right_pressed : bool,
+    pub moved : bool,
 }
 
 impl Mouse {
@@ -20,13 +26,266 @@ impl Mouse {
             y: 0,
             left_click : false,
             right_click : false,
+            left_pressed : false,
+            right_pressed : false,
+            moved : false,
+        }
+    }
+}
+
+pub const ACTION_COUNT : usize = 10;
+
+/// a control the game reads, any number of buttons can be bound to it
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    A,
+    B,
+    Restart,
+    Debug1,
+    Debug2,
+    Debug3,
+}
+
+impl Action {
+    pub const ALL : [Action; ACTION_COUNT] = [
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+        Action::A,
+        Action::B,
+        Action::Restart,
+        Action::Debug1,
+        Action::Debug2,
+        Action::Debug3,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::A => "a",
+            Action::B => "b",
+            Action::Restart => "restart",
+            Action::Debug1 => "debug_1",
+            Action::Debug2 => "debug_2",
+            Action::Debug3 => "debug_3",
         }
     }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Self::ALL.iter().find(|a| a.name() == name).copied()
+    }
+}
+
+/// a key, mouse button, controller button or stick direction that can be bound to an `Action`
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Binding {
+    Key(Scancode),
+    Mouse(MouseButton),
+    Controller(Button),
+    /// an axis pushed past the deadzone, in the positive direction if the bool is true
+    Axis(Axis, bool),
 }
 
+const AXIS_COUNT : usize = 6;
+/// the axes in the order of their sdl values, which index `Input::axes`
+const AXES : [Axis; AXIS_COUNT] = [Axis::LeftX, Axis::LeftY, Axis::RightX, Axis::RightY, Axis::TriggerLeft, Axis::TriggerRight];
+const DEFAULT_DEADZONE : i16 = 8000;
+pub const DEFAULT_REPEAT_DELAY : f64 = 0.18;
+pub const DEFAULT_REPEAT_RATE : f64 = 0.05;
+
+impl Binding {
+    /// the name used in the controls config file, ie `key:W`, `mouse:left`, `pad:a` or `axis:leftx+`
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(k) => format!("key:{}", k.name()),
+            Binding::Mouse(b) => format!("mouse:{}", match b {
+                MouseButton::Left => "left",
+                MouseButton::Middle => "middle",
+                MouseButton::Right => "right",
+                MouseButton::X1 => "x1",
+                MouseButton::X2 => "x2",
+                MouseButton::Unknown => "unknown",
+            }),
+            Binding::Controller(b) => format!("pad:{}", b.string()),
+            Binding::Axis(a, positive) => format!("axis:{}{}", a.string(), if *positive { "+" } else { "-" }),
+        }
+    }
+
+    /// whether the binding is on a controller rather than the keyboard or mouse
+    pub fn is_controller(&self) -> bool {
+        matches!(self, Binding::Controller(_) | Binding::Axis(..))
+    }
+
+    pub fn from_name(name: &str) -> Option<Binding> {
+        let (kind, button) = name.split_once(':')?;
+        match kind {
+            "key" => Scancode::from_name(button).map(Binding::Key),
+            "mouse" => match button {
+                "left" => Some(Binding::Mouse(MouseButton::Left)),
+                "middle" => Some(Binding::Mouse(MouseButton::Middle)),
+                "right" => Some(Binding::Mouse(MouseButton::Right)),
+                "x1" => Some(Binding::Mouse(MouseButton::X1)),
+                "x2" => Some(Binding::Mouse(MouseButton::X2)),
+                _ => None,
+            },
+            "pad" => Button::from_string(button).map(Binding::Controller),
+            "axis" => {
+                let positive = button.ends_with('+');
+                if !positive && !button.ends_with('-') {
+                    return None;
+                }
+                Axis::from_string(&button[..button.len() - 1]).map(|a| Binding::Axis(a, positive))
+            },
+            _ => None,
+        }
+    }
+}
+
+/// which buttons are bound to which actions
+#[derive(Clone)]
+pub struct Bindings {
+    binds: Vec<(Action, Binding)>,
+    /// how far a stick must be pushed before it counts as pressed, out of 32767
+    pub deadzone: i16,
+    /// seconds a direction is held before it starts repeating
+    pub repeat_delay: f64,
+    /// seconds between repeats once a direction is repeating
+    pub repeat_rate: f64,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bindings {
+    /// the default controls
+    pub fn new() -> Self {
+        let mut b = Bindings::empty();
+        b.bind(Action::Up, Binding::Key(Scancode::Up));
+        b.bind(Action::Up, Binding::Key(Scancode::W));
+        b.bind(Action::Up, Binding::Controller(Button::DPadUp));
+        b.bind(Action::Up, Binding::Axis(Axis::LeftY, false));
+        b.bind(Action::Left, Binding::Key(Scancode::Left));
+        b.bind(Action::Left, Binding::Key(Scancode::A));
+        b.bind(Action::Left, Binding::Controller(Button::DPadLeft));
+        b.bind(Action::Left, Binding::Axis(Axis::LeftX, false));
+        b.bind(Action::Down, Binding::Key(Scancode::Down));
+        b.bind(Action::Down, Binding::Key(Scancode::S));
+        b.bind(Action::Down, Binding::Controller(Button::DPadDown));
+        b.bind(Action::Down, Binding::Axis(Axis::LeftY, true));
+        b.bind(Action::Right, Binding::Key(Scancode::Right));
+        b.bind(Action::Right, Binding::Key(Scancode::D));
+        b.bind(Action::Right, Binding::Controller(Button::DPadRight));
+        b.bind(Action::Right, Binding::Axis(Axis::LeftX, true));
+        b.bind(Action::A, Binding::Key(Scancode::Z));
+        b.bind(Action::A, Binding::Key(Scancode::Comma));
+        b.bind(Action::A, Binding::Controller(Button::A));
+        b.bind(Action::B, Binding::Key(Scancode::X));
+        b.bind(Action::B, Binding::Key(Scancode::Period));
+        b.bind(Action::B, Binding::Controller(Button::B));
+        b.bind(Action::Restart, Binding::Key(Scancode::R));
+        b.bind(Action::Restart, Binding::Controller(Button::Back));
+        b.bind(Action::Debug1, Binding::Key(Scancode::F1));
+        b.bind(Action::Debug2, Binding::Key(Scancode::F2));
+        b.bind(Action::Debug3, Binding::Key(Scancode::F3));
+        b
+    }
+
+    fn empty() -> Self {
+        Bindings {
+            binds: Vec::new(),
+            deadzone: DEFAULT_DEADZONE,
+            repeat_delay: DEFAULT_REPEAT_DELAY,
+            repeat_rate: DEFAULT_REPEAT_RATE,
+        }
+    }
+
+    /// read bindings from a file with one `action = binding` pair per line,
+    /// and optionally `deadzone = n`, `repeat_delay = s` and `repeat_rate = s` lines.
+    /// Invalid lines are skipped, and an action left without a binding gets its default ones
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut b = Bindings::empty();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let valid = match line.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+                Some(("deadzone", value)) => value.parse().map(|v| b.deadzone = v).is_ok(),
+                Some(("repeat_delay", value)) => value.parse().map(|v| b.repeat_delay = v).is_ok(),
+                Some(("repeat_rate", value)) => value.parse().map(|v| b.repeat_rate = v).is_ok(),
+                Some((action, binding)) => match (Action::from_name(action), Binding::from_name(binding)) {
+                    (Some(action), Some(binding)) => {
+                        b.bind(action, binding);
+                        true
+                    },
+                    _ => false,
+                },
+                None => false,
+            };
+            if !valid {
+                println!("skipping invalid line {} of {}: {}", i + 1, path.to_string_lossy(), line);
+            }
+        }
+        let unbound: Vec<(Action, Binding)> = Bindings::new().binds.into_iter()
+            .filter(|(action, _)| !b.binds.iter().any(|(a, _)| a == action))
+            .collect();
+        for (action, binding) in unbound {
+            b.bind(action, binding);
+        }
+        Ok(b)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut text = String::from("# action = key:<name> | mouse:<button> | pad:<button> | axis:<axis><+/->\n");
+        text.push_str(&format!("deadzone = {}\n", self.deadzone));
+        text.push_str(&format!("repeat_delay = {}\n", self.repeat_delay));
+        text.push_str(&format!("repeat_rate = {}\n", self.repeat_rate));
+        for (action, binding) in self.binds.iter() {
+            text.push_str(&format!("{} = {}\n", action.name(), binding.name()));
+        }
+        std::fs::write(path, text).map_err(|e| e.to_string())
+    }
+
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        if !self.binds.contains(&(action, binding)) {
+            self.binds.push((action, binding));
+        }
+    }
+
+    /// remove every binding of an action
+    pub fn clear(&mut self, action: Action) {
+        self.binds.retain(|b| b.0 != action);
+    }
+
+    /// replace the action's bindings on the same device as `binding`, so rebinding a key leaves the controller alone
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        self.binds.retain(|b| b.0 != action || b.1.is_controller() != binding.is_controller());
+        self.bind(action, binding);
+    }
+
+    pub fn get(&self, action: Action) -> Vec<Binding> {
+        self.binds.iter().filter(|b| b.0 == action).map(|b| b.1).collect()
+    }
+
+    fn actions(&self, binding: Binding) -> impl Iterator<Item = Action> + '_ {
+        self.binds.iter().filter(move |b| b.1 == binding).map(|b| b.0)
+    }
+}
 
 /// Holds character typed that frame, and the state of some useful buttons for typing
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Input {
     pub up        : bool,
     pub down      : bool,
@@ -39,6 +298,23 @@ pub struct Input {
     pub debug_2   : bool,
     pub debug_3   : bool,
     pub mouse     : Mouse,
+    /// the last key or button pressed this frame, bound or not, used for rebinding controls
+    pub last_pressed : Option<Binding>,
+    /// text typed this frame, only filled while sdl text input is started
+    pub text : String,
+    /// text an input method is still composing, replaced by `text` once committed
+    pub composition : String,
+    /// backspace presses this frame, including key repeats
+    pub backspaces : u32,
+    /// enter was pressed this frame
+    pub enter : bool,
+    held : [u8; ACTION_COUNT],
+    pressed : [bool; ACTION_COUNT],
+    released : [bool; ACTION_COUNT],
+    held_time : [f64; ACTION_COUNT],
+    axes : [i8; AXIS_COUNT],
+    /// controller buttons that are down, so they can be released if the controller is unplugged
+    buttons : Vec<Button>,
 }
 
 impl Input {
@@ -56,50 +332,195 @@ impl Input {
             debug_1: false,
             debug_2: false,
             debug_3: false,
+            last_pressed: None,
+            text: String::new(),
+            composition: String::new(),
+            backspaces: 0,
+            enter: false,
+            held: [0; ACTION_COUNT],
+            pressed: [false; ACTION_COUNT],
+            released: [false; ACTION_COUNT],
+            held_time: [0.0; ACTION_COUNT],
+            axes: [0; AXIS_COUNT],
+            buttons: Vec::new(),
         }
     }
 
-    pub fn handle_event(&mut self, event: &Event) {
-        if event.is_keyboard() {
-            self.handle_keyboard(event);
-        } else if event.is_mouse() {
+    /// update from an event, `controller::player_input` picks which player's `Input` an event goes to
+    pub fn handle_event(&mut self, event: &Event, bindings: &Bindings) {
+        if event.is_mouse() {
             self.handle_mouse(event);
         }
+        self.handle_text(event);
+        match event {
+            Event::ControllerAxisMotion { axis, value, .. } => {
+                self.handle_axis(*axis, *value, bindings);
+                return;
+            },
+            Event::ControllerDeviceRemoved { .. } => {
+                self.release_controller(bindings);
+                return;
+            },
+            Event::ControllerButtonDown { button, .. } => {
+                if self.buttons.contains(button) {
+                    return;
+                }
+                self.buttons.push(*button);
+            },
+            Event::ControllerButtonUp { button, .. } => {
+                if !self.buttons.contains(button) {
+                    return;
+                }
+                self.buttons.retain(|b| b != button);
+            },
+            _ => (),
+        }
+        let binding = match event {
+            Event::KeyDown { scancode: Some(k), repeat: false, .. } => Some((Binding::Key(*k), true)),
+            Event::KeyUp { scancode: Some(k), .. } => Some((Binding::Key(*k), false)),
+            Event::MouseButtonDown { mouse_btn, .. } => Some((Binding::Mouse(*mouse_btn), true)),
+            Event::MouseButtonUp { mouse_btn, .. } => Some((Binding::Mouse(*mouse_btn), false)),
+            Event::ControllerButtonDown { button, .. } => Some((Binding::Controller(*button), true)),
+            Event::ControllerButtonUp { button, .. } => Some((Binding::Controller(*button), false)),
+            _ => None,
+        };
+        if let Some((binding, down)) = binding {
+            self.handle_binding(binding, down, bindings);
+        }
+    }
+
+    /// start a new frame, call once a frame before handling that frame's events.
+    /// Clears the last frame's presses and releases and adds `timer` to the held time of held actions
+    pub fn advance(&mut self, timer: f64) {
+        for action in Action::ALL {
+            let i = action as usize;
+            if self.held(action) && !self.pressed[i] {
+                self.held_time[i] += timer;
+            }
+        }
+        self.pressed = [false; ACTION_COUNT];
+        self.released = [false; ACTION_COUNT];
+        self.last_pressed = None;
+        self.text.clear();
+        self.backspaces = 0;
+        self.enter = false;
+        self.mouse.left_pressed = false;
+        self.mouse.right_pressed = false;
+        self.mouse.moved = false;
+    }
+
+    /// whether the action went from released to held this frame
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed[action as usize]
+    }
+
+    /// whether the action went from held to released this frame
+    pub fn released(&self, action: Action) -> bool {
+        self.released[action as usize]
+    }
+
+    /// seconds the action has been held for, not counting the frame it was pressed
+    pub fn held_time(&self, action: Action) -> f64 {
+        if self.held(action) { self.held_time[action as usize] } else { 0.0 }
+    }
+
+    /// whether any button bound to the action is held
+    pub fn held(&self, action: Action) -> bool {
+        match action {
+            Action::Up => self.up,
+            Action::Down => self.down,
+            Action::Left => self.left,
+            Action::Right => self.right,
+            Action::A => self.a,
+            Action::B => self.b,
+            Action::Restart => self.restart,
+            Action::Debug1 => self.debug_1,
+            Action::Debug2 => self.debug_2,
+            Action::Debug3 => self.debug_3,
+        }
+    }
+
+    fn set(&mut self, action: Action, value: bool) {
+        match action {
+            Action::Up => self.up = value,
+            Action::Down => self.down = value,
+            Action::Left => self.left = value,
+            Action::Right => self.right = value,
+            Action::A => self.a = value,
+            Action::B => self.b = value,
+            Action::Restart => self.restart = value,
+            Action::Debug1 => self.debug_1 = value,
+            Action::Debug2 => self.debug_2 = value,
+            Action::Debug3 => self.debug_3 = value,
+        }
+    }
+
+    fn handle_binding(&mut self, binding: Binding, down: bool, bindings: &Bindings) {
+        if down {
+            self.last_pressed = Some(binding);
+        }
+        for action in bindings.actions(binding) {
+            // count held buttons so releasing one of two bound buttons keeps the action held
+            let i = action as usize;
+            let was_held = self.held[i] > 0;
+            self.held[i] = if down { self.held[i].saturating_add(1) } else { self.held[i].saturating_sub(1) };
+            let value = self.held[i] > 0;
+            if value && !was_held {
+                self.pressed[i] = true;
+                self.held_time[i] = 0.0;
+            }
+            if !value && was_held {
+                self.released[i] = true;
+            }
+            self.set(action, value);
+        }
     }
 
-    fn handle_keyboard(&mut self, event : &Event) {
-        let mut key_down = false;
-        let key = match event {
-            Event::KeyDown {
-                scancode: k,
-                ..
-            } => {
-                key_down = true;
-                k
+    fn handle_text(&mut self, event: &Event) {
+        match event {
+            Event::TextInput { text, .. } => {
+                self.text.push_str(text);
+                self.composition.clear();
+            },
+            Event::TextEditing { text, .. } => {
+                self.composition.clear();
+                self.composition.push_str(text);
             },
-            Event::KeyUp {
-                scancode: k,
-                ..
-            } => k,
-            _ => &None
+            Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => self.backspaces += 1,
+            Event::KeyDown { keycode: Some(Keycode::Return | Keycode::KpEnter), repeat: false, .. } => self.enter = true,
+            _ => (),
+        }
+    }
+
+    /// let go of every controller button and centre every axis, as if they had been released
+    fn release_controller(&mut self, bindings: &Bindings) {
+        for button in std::mem::take(&mut self.buttons) {
+            self.handle_binding(Binding::Controller(button), false, bindings);
+        }
+        for axis in AXES {
+            self.handle_axis(axis, 0, bindings);
+        }
+    }
+
+    /// treat a stick direction as a button that is pressed when pushed past the deadzone
+    fn handle_axis(&mut self, axis: Axis, value: i16, bindings: &Bindings) {
+        let dir = if value > bindings.deadzone {
+            1
+        } else if value < -bindings.deadzone {
+            -1
+        } else {
+            0
         };
-        match key {
-            Some(k) => {
-                match k {
-                    Scancode::Up | Scancode::W => self.up    = key_down,
-                    Scancode::Left | Scancode::A => self.left  = key_down,
-                    Scancode::Down | Scancode::S => self.down  = key_down,
-                    Scancode::Right | Scancode::D => self.right = key_down,
-                    Scancode::Z | Scancode::Comma => self.a = key_down,
-                    Scancode::X | Scancode::Period => self.b = key_down,
-                    Scancode::R => self.restart = key_down,
-                    Scancode::F1 => self.debug_1 = key_down,
-                    Scancode::F2 => self.debug_2 = key_down,
-                    Scancode::F3 => self.debug_3 = key_down,
-                    _ => {}
-                }
-            }
-            _ => {}
+        let prev = self.axes[axis as usize];
+        if dir == prev {
+            return;
+        }
+        self.axes[axis as usize] = dir;
+        if prev != 0 {
+            self.handle_binding(Binding::Axis(axis, prev > 0), false, bindings);
+        }
+        if dir != 0 {
+            self.handle_binding(Binding::Axis(axis, dir > 0), true, bindings);
         }
     }
 
@@ -109,13 +530,19 @@ impl Input {
             Event::MouseMotion { x, y, .. } => {
                 self.mouse.x = *x;
                 self.mouse.y = *y;
+                self.mouse.moved = true;
                 None
             },
-            Event::MouseButtonDown { mouse_btn, ..} => {
+            // a click or tap can come without any motion before it, so it moves the mouse too
+            Event::MouseButtonDown { mouse_btn, x, y, ..} => {
+                self.mouse.x = *x;
+                self.mouse.y = *y;
                 btn_down = true;
                 Some(mouse_btn)
             },
-            Event::MouseButtonUp { mouse_btn, .. } => {
+            Event::MouseButtonUp { mouse_btn, x, y, .. } => {
+                self.mouse.x = *x;
+                self.mouse.y = *y;
                 btn_down = false;
                 Some(mouse_btn)
             }
@@ -123,11 +550,275 @@ impl Input {
         };
         match btn {
             Some(btn) => match btn {
-                MouseButton::Left => self.mouse.left_click = btn_down,
-                MouseButton::Right => self.mouse.right_click = btn_down,
+                MouseButton::Left => {
+                    self.mouse.left_pressed |= btn_down && !self.mouse.left_click;
+                    self.mouse.left_click = btn_down;
+                },
+                MouseButton::Right => {
+                    self.mouse.right_pressed |= btn_down && !self.mouse.right_click;
+                    self.mouse.right_click = btn_down;
+                },
                 _ => (),
             }
             None => (),
         }
     }
 }
+
+/// the fixed step auto repeat counts time in, in seconds
+pub const REPEAT_TICK : f64 = 1.0 / 120.0;
+
+/// delayed auto shift, a held action fires when pressed, again after `delay` seconds,
+/// then every `rate` seconds until released.
+/// Time is counted in whole `REPEAT_TICK`s from the press, so repeats land on the same ticks whatever the frame times are
+#[derive(Clone, Copy)]
+pub struct AutoRepeat {
+    pub delay: f64,
+    pub rate: f64,
+    /// ticks until the next repeat
+    ticks: i64,
+    /// frame time that hasn't made up a whole tick yet
+    time: f64,
+}
+
+impl AutoRepeat {
+    pub fn new(delay: f64, rate: f64) -> Self {
+        AutoRepeat { delay, rate, ticks: 0, time: 0.0 }
+    }
+
+    /// how many times the action fires this frame, `timer` is the time since the last update
+    pub fn update(&mut self, input: &Input, action: Action, timer: f64) -> usize {
+        if input.pressed(action) {
+            self.ticks = to_ticks(self.delay);
+            self.time = 0.0;
+            return 1;
+        }
+        if !input.held(action) {
+            return 0;
+        }
+        self.time += timer;
+        // a little slack so a frame of exactly n ticks isn't rounded down to n - 1
+        let ticks = (self.time / REPEAT_TICK + 1e-6).floor().max(0.0);
+        self.time = (self.time - ticks * REPEAT_TICK).max(0.0);
+        self.tick(ticks as i64)
+    }
+
+    /// step by whole ticks, returns how many times the action fires
+    fn tick(&mut self, ticks: i64) -> usize {
+        let rate = to_ticks(self.rate);
+        self.ticks -= ticks;
+        let mut count = 0;
+        while self.ticks <= 0 {
+            count += 1;
+            if rate <= 0 {
+                // a rate of less than a tick repeats once every update
+                self.ticks = 0;
+                break;
+            }
+            self.ticks += rate;
+        }
+        count
+    }
+}
+
+fn to_ticks(seconds: f64) -> i64 {
+    (seconds / REPEAT_TICK).round() as i64
+}
+
+#[cfg(test)]
+mod input_tests {
+    use super::*;
+    use crate::temp_path;
+
+    #[test]
+    fn test_binding_names() {
+        let bindings = [
+            Binding::Key(Scancode::W),
+            Binding::Key(Scancode::Return),
+            Binding::Mouse(MouseButton::Right),
+            Binding::Controller(Button::DPadUp),
+            Binding::Axis(Axis::LeftX, true),
+            Binding::Axis(Axis::TriggerRight, false),
+        ];
+        for b in bindings.iter() {
+            assert!(Binding::from_name(&b.name()) == Some(*b));
+        }
+        assert!(Binding::from_name("axis:leftx+") == Some(Binding::Axis(Axis::LeftX, true)));
+        assert!(Binding::from_name("axis:leftx").is_none());
+        assert!(Binding::from_name("key").is_none());
+        assert!(Binding::from_name("mouse:wheel").is_none());
+        assert!(Binding::from_name("joystick:a").is_none());
+        for a in Action::ALL.iter() {
+            assert!(Action::from_name(a.name()) == Some(*a));
+        }
+    }
+
+    fn press(input: &mut Input, bindings: &Bindings, down: bool) {
+        input.handle_binding(Binding::Key(Scancode::Right), down, bindings);
+    }
+
+    #[test]
+    fn test_auto_repeat() {
+        let bindings = Bindings::new();
+        let mut input = Input::new();
+        let mut repeat = AutoRepeat::new(0.5, 0.1);
+        input.advance(0.0);
+        press(&mut input, &bindings, true);
+        assert!(repeat.update(&input, Action::Right, 0.0) == 1);
+        // nothing until the delay has passed
+        input.advance(0.25);
+        assert!(repeat.update(&input, Action::Right, 0.25) == 0);
+        input.advance(0.25);
+        assert!(repeat.update(&input, Action::Right, 0.25) == 1);
+        // a long frame fires every repeat it covered
+        input.advance(0.35);
+        assert!(repeat.update(&input, Action::Right, 0.35) == 3);
+        input.advance(0.0);
+        press(&mut input, &bindings, false);
+        assert!(repeat.update(&input, Action::Right, 1.0) == 0);
+        // pressing again restarts the delay
+        input.advance(0.0);
+        press(&mut input, &bindings, true);
+        assert!(repeat.update(&input, Action::Right, 0.0) == 1);
+        input.advance(0.4);
+        assert!(repeat.update(&input, Action::Right, 0.4) == 0);
+    }
+
+    /// the frames a held action fires on, for frames of the given lengths after the press
+    fn repeat_frames(frames: &[f64]) -> Vec<(f64, usize)> {
+        let bindings = Bindings::new();
+        let mut input = Input::new();
+        let mut repeat = AutoRepeat::new(DEFAULT_REPEAT_DELAY, DEFAULT_REPEAT_RATE);
+        press(&mut input, &bindings, true);
+        assert!(repeat.update(&input, Action::Right, 0.0) == 1);
+        let mut time = 0.0;
+        let mut fired = Vec::new();
+        for dt in frames {
+            input.advance(*dt);
+            time += dt;
+            let count = repeat.update(&input, Action::Right, *dt);
+            if count > 0 {
+                fired.push((time, count));
+            }
+        }
+        fired
+    }
+
+    #[test]
+    fn test_auto_repeat_deterministic() {
+        // uneven frame times, the same every run
+        let frames : Vec<f64> = (0..90).map(|i| [1.0 / 60.0, 1.0 / 144.0, 1.0 / 30.0][i % 3]).collect();
+        assert!(repeat_frames(&frames) == repeat_frames(&frames));
+
+        // repeats are on whole ticks from the press, so different frame rates fire the same number of times by the same tick
+        let fast = repeat_frames(&[1.0 / 120.0; 120]);
+        let slow = repeat_frames(&[1.0 / 30.0; 30]);
+        let total = |fired: &Vec<(f64, usize)>| fired.iter().map(|f| f.1).sum::<usize>();
+        assert!(total(&fast) == total(&slow));
+        assert!(fast.len() == total(&fast));
+        for (time, count) in slow.iter() {
+            let by_then : usize = fast.iter().filter(|f| f.0 <= time + 1e-9).map(|f| f.1).sum();
+            let slow_by_then : usize = slow.iter().filter(|f| f.0 <= time + 1e-9).map(|f| f.1).sum();
+            assert!(by_then == slow_by_then && *count > 0);
+        }
+    }
+
+    #[test]
+    fn test_axis_deadzone() {
+        let bindings = Bindings::new();
+        let mut input = Input::new();
+        let stick = |value| Event::ControllerAxisMotion { timestamp: 0, which: 0, axis: Axis::LeftX, value };
+        input.handle_event(&stick(bindings.deadzone), &bindings);
+        assert!(!input.held(Action::Right) && !input.held(Action::Left));
+        input.handle_event(&stick(bindings.deadzone + 1), &bindings);
+        assert!(input.pressed(Action::Right) && input.held(Action::Right));
+        input.advance(0.1);
+        input.handle_event(&stick(i16::MAX), &bindings);
+        assert!(!input.pressed(Action::Right) && input.held(Action::Right));
+        // crossing straight to the other side releases one direction and presses the other
+        input.handle_event(&stick(-bindings.deadzone - 1), &bindings);
+        assert!(input.released(Action::Right) && !input.held(Action::Right));
+        assert!(input.pressed(Action::Left));
+        input.advance(0.1);
+        input.handle_event(&stick(0), &bindings);
+        assert!(input.released(Action::Left) && !input.held(Action::Left));
+    }
+
+    #[test]
+    fn test_controller_buttons() {
+        let bindings = Bindings::new();
+        let mut input = Input::new();
+        input.handle_event(&Event::ControllerButtonDown { timestamp: 0, which: 0, button: Button::DPadUp }, &bindings);
+        assert!(input.pressed(Action::Up) && input.up);
+        assert!(input.last_pressed == Some(Binding::Controller(Button::DPadUp)));
+        input.handle_event(&Event::ControllerButtonUp { timestamp: 0, which: 0, button: Button::DPadUp }, &bindings);
+        assert!(input.released(Action::Up) && !input.up);
+    }
+
+    #[test]
+    fn test_controller_removed() {
+        let bindings = Bindings::new();
+        let mut input = Input::new();
+        input.handle_event(&Event::ControllerButtonDown { timestamp: 0, which: 0, button: Button::DPadUp }, &bindings);
+        input.handle_event(&Event::ControllerButtonDown { timestamp: 0, which: 0, button: Button::A }, &bindings);
+        input.handle_event(&Event::ControllerAxisMotion { timestamp: 0, which: 0, axis: Axis::LeftX, value: i16::MIN }, &bindings);
+        input.handle_binding(Binding::Key(Scancode::Right), true, &bindings);
+        input.advance(0.1);
+        assert!(input.held(Action::Up) && input.held(Action::A) && input.held(Action::Left));
+        input.handle_event(&Event::ControllerDeviceRemoved { timestamp: 0, which: 0 }, &bindings);
+        assert!(!input.held(Action::Up) && !input.held(Action::A) && !input.held(Action::Left));
+        assert!(input.released(Action::Up) && input.released(Action::A) && input.released(Action::Left));
+        // keys aren't released with the controller
+        assert!(input.held(Action::Right));
+        // plugging back in and pressing again works as normal
+        input.handle_event(&Event::ControllerButtonDown { timestamp: 0, which: 1, button: Button::DPadUp }, &bindings);
+        assert!(input.pressed(Action::Up));
+    }
+
+    #[test]
+    fn test_rebind() {
+        let mut b = Bindings::new();
+        let pad = b.get(Action::A).into_iter().filter(|b| b.is_controller()).collect::<Vec<Binding>>();
+        assert!(!pad.is_empty());
+        b.rebind(Action::A, Binding::Key(Scancode::Space));
+        let keys = b.get(Action::A).into_iter().filter(|b| !b.is_controller()).collect::<Vec<Binding>>();
+        assert!(keys == vec![Binding::Key(Scancode::Space)]);
+        assert!(b.get(Action::A).into_iter().filter(|b| b.is_controller()).collect::<Vec<Binding>>() == pad);
+
+        b.rebind(Action::A, Binding::Axis(Axis::TriggerLeft, true));
+        assert!(b.get(Action::A) == vec![Binding::Key(Scancode::Space), Binding::Axis(Axis::TriggerLeft, true)]);
+        // other actions keep their bindings
+        assert!(b.get(Action::B) == Bindings::new().get(Action::B));
+    }
+
+    #[test]
+    fn test_save_load() {
+        let path = temp_path("controls_round_trip.cfg");
+        let mut b = Bindings::new();
+        b.clear(Action::A);
+        b.bind(Action::A, Binding::Key(Scancode::Space));
+        b.deadzone = 1234;
+        b.repeat_delay = 0.25;
+        b.repeat_rate = 0.1;
+        b.save(&path).unwrap();
+        let loaded = Bindings::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.binds == b.binds);
+        assert!(loaded.deadzone == 1234);
+        assert!(loaded.repeat_delay == 0.25);
+        assert!(loaded.repeat_rate == 0.1);
+    }
+
+    #[test]
+    fn test_load_skips_invalid_lines() {
+        let path = temp_path("controls_invalid.cfg");
+        std::fs::write(&path, "deadzone = lots\nup = key:W\nup = key:NotAKey\njump = key:Space\nb = pad:b\n").unwrap();
+        let loaded = Bindings::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.deadzone == DEFAULT_DEADZONE);
+        assert!(loaded.get(Action::Up) == vec![Binding::Key(Scancode::W)]);
+        assert!(loaded.get(Action::B) == vec![Binding::Controller(Button::B)]);
+        // actions missing from the file keep their defaults
+        assert!(loaded.get(Action::Left) == Bindings::new().get(Action::Left));
+    }
+}
@@ -1,19 +1,32 @@
-use sdl2::controller::GameController;
-use sdl2::render::{TextureCreator, Texture, Canvas};
-use sdl2::video::Window;
-use sdl2::image::LoadTexture;
+use sdl2::render::{TextureCreator, Texture, Canvas, RenderTarget};
+use sdl2::image::LoadSurface;
 use sdl2::pixels::Color;
 use sdl2::ttf;
+use sdl2::surface::Surface;
 
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use std::clone::Clone;
 
 pub mod input;
+pub mod controller;
 use geometry::*;
 pub mod map;
 pub mod camera;
+pub mod screen;
+pub mod render;
+pub mod atlas;
+pub mod animation;
+pub mod aseprite;
 pub mod hex;
+pub mod highscore;
+pub mod scene;
+pub mod ui;
+pub mod glyph_cache;
+pub mod bitmap_font;
+use bitmap_font::BitmapFont;
+use glyph_cache::{GlyphCache, GlyphCacheStats, GlyphFont};
 
 trait RectConversion {
     fn new_from_sdl_rect(sdl_rect : &sdl2::rect::Rect) -> Self;
@@ -40,9 +53,13 @@ impl RectConversion for Rect{
 pub mod resource {
 //! represent sdl2 textures and fonts as cheap structs that hold indexes for resource managers
 
+    /// an image, which may be part of a larger texture shared with other images
     #[derive(Clone, Copy)]
     pub struct Texture {
         pub id:     usize,
+        /// where the image is in the texture `id`
+        pub x:      u32,
+        pub y:      u32,
         pub width:  u32,
         pub height: u32
     }
@@ -86,13 +103,43 @@ impl Colour {
     }
 }
 
+/// mirror a sprite, `diagonal` swaps the x and y axes and is applied before the others, like tiled does
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Flip {
+    pub horizontal: bool,
+    pub vertical: bool,
+    pub diagonal: bool,
+}
+
+impl Flip {
+    /// the same flip as an sdl rotation in degrees and horizontal and vertical flips
+    fn to_sdl(self) -> (f64, bool, bool) {
+        if self.diagonal {
+            // swapping the axes is flipping vertically and then turning a quarter clockwise,
+            // the flips applied after it turn into the other axis once rotated
+            (90.0, self.vertical, !self.horizontal)
+        } else {
+            (0.0, self.horizontal, self.vertical)
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct GameObject {
     pub texture: resource::Texture,
     pub rect: Rect,
     tex_rect: Rect,
     parallax: Vec2,
-    pub colour: Colour
+    pub colour: Colour,
+    /// objects on lower layers are drawn first, see `camera::layer`
+    pub layer: i32,
+    /// orders objects within a layer, lower is drawn first
+    pub depth: f64,
+    /// clockwise rotation in degrees around `origin`
+    pub angle: f64,
+    /// the point to rotate around relative to the top left of `rect`, `None` is the centre
+    pub origin: Option<Vec2>,
+    pub flip: Flip,
 }
 
 impl GameObject {
@@ -104,6 +151,11 @@ impl GameObject {
             tex_rect : r,
             parallax: Vec2::new(1.0, 1.0),
             colour: Colour::white(),
+            layer: 0,
+            depth: 0.0,
+            angle: 0.0,
+            origin: None,
+            flip: Flip::default(),
         }
     }
     pub fn new(texture : resource::Texture, rect : Rect, tex_rect: Rect, parallax : Vec2, colour: Colour) -> Self {
@@ -113,6 +165,11 @@ impl GameObject {
             tex_rect,
             parallax,
             colour,
+            layer: 0,
+            depth: 0.0,
+            angle: 0.0,
+            origin: None,
+            flip: Flip::default(),
         }
     }
 }
@@ -124,6 +181,11 @@ pub struct TextureDraw {
     pub tex_rect : Rect,
     pub colour : Colour,
     pub tex  : resource::Texture,
+    /// clockwise rotation in degrees around `origin`
+    pub angle : f64,
+    /// relative to the top left of `draw_rect`, `None` is the centre
+    pub origin : Option<Vec2>,
+    pub flip : Flip,
 }
 
 impl TextureDraw {
@@ -132,16 +194,33 @@ impl TextureDraw {
             draw_rect,
             tex_rect,
             colour,
-            tex
+            tex,
+            angle: 0.0,
+            origin: None,
+            flip: Flip::default(),
         }
     }
 }
 
+/// how often changed files are looked for while watching, in seconds
+const WATCH_INTERVAL : f64 = 0.5;
+/// drawn in place of textures that failed to load
+pub const DEFAULT_FALLBACK_TEXTURE : &str = "textures/error.png";
+
 /// stores textures that are referenced by a `resource::Texture` object
 pub struct TextureManager<'a, T> {
     texture_creator : &'a TextureCreator<T>,
-    loaded_texture_paths : HashMap<String,  usize>,
-    textures     : Vec<Texture<'a>>,
+    loaded_texture_paths : HashMap<String,  resource::Texture>,
+    /// freed textures leave a `None` so the ids of the others don't change
+    textures     : Vec<Option<Texture<'a>>>,
+    /// how many textures there were when each open scope began
+    scopes : Vec<usize>,
+    /// when each file that was loaded was last changed, for reloading
+    modified : HashMap<String, SystemTime>,
+    watching : bool,
+    watch_timer : f64,
+    fallback_path : PathBuf,
+    fallback_texture : Option<resource::Texture>,
 }
 
 impl<'a, T> TextureManager<'a, T> {
@@ -151,67 +230,420 @@ impl<'a, T> TextureManager<'a, T> {
             texture_creator : tex_creator,
             loaded_texture_paths: HashMap::new(),
             textures : Vec::new(),
+            scopes: Vec::new(),
+            modified: HashMap::new(),
+            watching: false,
+            watch_timer: 0.0,
+            fallback_path: PathBuf::from(DEFAULT_FALLBACK_TEXTURE),
+            fallback_texture: None,
         }
     }
 /// load a texture to memory and get a `resource::Texture` object that references it
+    /// a `.aseprite` file is loaded as a sheet of all its frames, see `aseprite::load_animation`.
+    /// If the file can't be loaded the fallback texture is returned instead
     pub fn load(&mut self, path : &Path) -> Result<resource::Texture, String> {
         let path_as_string = path.to_string_lossy().to_string();
-        let tex_index = match self.loaded_texture_paths.contains_key(&path_as_string) {
-            true => self.loaded_texture_paths[&path_as_string],
-            false => {
-                self.textures.push(self.texture_creator.load_texture(path)?);
-                self.loaded_texture_paths.insert(path_as_string, self.textures.len() - 1);
+        if let Some(tex) = self.loaded_texture_paths.get(&path_as_string) {
+            return Ok(*tex);
+        }
+        let surface = match load_surface(path) {
+            Ok(s) => s,
+            Err(e) => {
+                println!("failed to load {}: {}, using a placeholder", path_as_string, e);
+                return self.fallback();
+            },
+        };
+        let tex = self.add_surface(&path_as_string, &surface)?;
+        self.record_modified(&path_as_string);
+        Ok(tex)
+    }
+
+    /// make a texture from an image in memory, stored under `name` as if it was loaded from that path
+    pub fn add_surface(&mut self, name: &str, surface: &Surface) -> Result<resource::Texture, String> {
+        if let Some(tex) = self.loaded_texture_paths.get(name) {
+            return Ok(*tex);
+        }
+        self.textures.push(Some(self.texture_creator.create_texture_from_surface(surface).map_err(|e| e.to_string())?));
+        println!("loaded: {}", name);
+        let tex = resource::Texture {
+            id: self.textures.len() - 1,
+            x: 0,
+            y: 0,
+            width: surface.width(),
+            height: surface.height(),
+        };
+        self.loaded_texture_paths.insert(name.to_string(), tex);
+        Ok(tex)
+    }
+
+    /// make a texture for each page of an atlas, loading any of its images after this
+    /// gives the part of the page it was packed into
+    pub fn load_atlas(&mut self, atlas: &atlas::Atlas) -> Result<(), String> {
+        let first_page = self.textures.len();
+        for page in atlas.pages.iter() {
+            self.textures.push(Some(self.texture_creator.create_texture_from_surface(page).map_err(|e| e.to_string())?));
+        }
+        for (path, entry) in atlas.entries.iter() {
+            self.loaded_texture_paths.insert(path.clone(), resource::Texture {
+                id: first_page + entry.page,
+                x: entry.rect.x() as u32,
+                y: entry.rect.y() as u32,
+                width: entry.rect.width(),
+                height: entry.rect.height(),
+            });
+            self.record_modified(path);
+        }
+        println!("loaded atlas: {} images in {} pages", atlas.entries.len(), atlas.pages.len());
+        Ok(())
+    }
 
-                println!("loaded: {}", path.to_str().unwrap());
+    /// the image used for textures that fail to load, if it can't be loaded either a built in checkerboard is used
+    pub fn set_fallback(&mut self, path: &Path) {
+        self.fallback_path = path.to_path_buf();
+        self.fallback_texture = None;
+    }
 
-                self.textures.len() - 1
+    /// the placeholder texture, it isn't stored under any path so it is never reloaded or freed by path
+    pub fn fallback(&mut self) -> Result<resource::Texture, String> {
+        if let Some(tex) = self.fallback_texture {
+            return Ok(tex);
+        }
+        let surface = match load_surface(&self.fallback_path) {
+            Ok(s) => s,
+            Err(e) => {
+                println!("failed to load fallback texture {}: {}", self.fallback_path.to_string_lossy(), e);
+                checkerboard()?
             },
         };
-        let last_tex = &self.textures[tex_index];
-        Ok(
-        resource::Texture {
-            id: tex_index,
-            width: last_tex.query().width,
-            height: last_tex.query().height,
-        })
+        self.textures.push(Some(self.texture_creator.create_texture_from_surface(&surface).map_err(|e| e.to_string())?));
+        let tex = resource::Texture {
+            id: self.textures.len() - 1,
+            x: 0,
+            y: 0,
+            width: surface.width(),
+            height: surface.height(),
+        };
+        self.fallback_texture = Some(tex);
+        Ok(tex)
+    }
+
+    /// forget a loaded path, its texture is freed once no other path uses it.
+    /// Drawing with a `resource::Texture` that was freed draws the fallback texture
+    pub fn free(&mut self, path: &Path) {
+        let path = path.to_string_lossy().to_string();
+        self.modified.remove(&path);
+        if let Some(tex) = self.loaded_texture_paths.remove(&path) {
+            if !self.loaded_texture_paths.values().any(|t| t.id == tex.id) {
+                self.textures[tex.id] = None;
+            }
+        }
+    }
+
+    /// textures loaded after this are freed by the matching `end_scope`,
+    /// textures that were already loaded stay loaded even if they are loaded again in the scope
+    pub fn begin_scope(&mut self) {
+        self.scopes.push(self.textures.len());
+    }
 
+    pub fn end_scope(&mut self) {
+        let start = match self.scopes.pop() {
+            Some(s) => s,
+            None => return,
+        };
+        for texture in self.textures[start..].iter_mut() {
+            *texture = None;
+        }
+        if self.fallback_texture.map(|f| f.id >= start).unwrap_or(false) {
+            self.fallback_texture = None;
+        }
+        let modified = &mut self.modified;
+        self.loaded_texture_paths.retain(|path, tex| {
+            if tex.id >= start {
+                modified.remove(path);
+            }
+            tex.id < start
+        });
     }
+
+    /// how many textures are in memory
+    pub fn loaded(&self) -> usize {
+        self.textures.iter().filter(|t| t.is_some()).count()
+    }
+
+    /// look for changes to the files textures were loaded from in `update`, for seeing art edits while the game runs
+    pub fn watch(&mut self, watching: bool) {
+        self.watching = watching;
+    }
+
+    /// reload changed files every so often while watching
+    pub fn update(&mut self, timer: f64) {
+        if !self.watching {
+            return;
+        }
+        self.watch_timer += timer;
+        if self.watch_timer < WATCH_INTERVAL {
+            return;
+        }
+        self.watch_timer = 0.0;
+        self.reload_changed();
+    }
+
+    /// reload every loaded file that has changed on disk, keeping its `resource::Texture`.
+    /// An image that changed size keeps its old size in any `resource::Texture` already handed out,
+    /// and an image in an atlas can't change size at all
+    pub fn reload_changed(&mut self) {
+        let changed: Vec<String> = self.modified.iter()
+            .filter(|(path, time)| file_modified(path).map(|t| t != **time).unwrap_or(false))
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in changed {
+            self.record_modified(&path);
+            match self.reload(&path) {
+                Ok(()) => println!("reloaded: {}", path),
+                Err(e) => println!("failed to reload {}: {}", path, e),
+            }
+        }
+    }
+
+    fn reload(&mut self, path: &str) -> Result<(), String> {
+        let tex = match self.loaded_texture_paths.get(path) {
+            Some(t) => *t,
+            None => return Ok(()),
+        };
+        let surface = load_surface(Path::new(path))?;
+        let texture = self.textures[tex.id].as_mut().ok_or_else(|| String::from("texture was freed"))?;
+        let query = texture.query();
+        if surface.size() == (tex.width, tex.height) {
+            // write over the old image, which may be part of an atlas page
+            let surface = surface.convert_format(query.format)?;
+            let pitch = surface.pitch() as usize;
+            let rect = sdl2::rect::Rect::new(tex.x as i32, tex.y as i32, tex.width, tex.height);
+            return surface.with_lock(|pixels| texture.update(rect, pixels, pitch)).map_err(|e| e.to_string());
+        }
+        if tex.x != 0 || tex.y != 0 || (query.width, query.height) != (tex.width, tex.height) {
+            return Err(String::from("images in an atlas can't change size, build the atlas again"));
+        }
+        self.textures[tex.id] = Some(self.texture_creator.create_texture_from_surface(&surface).map_err(|e| e.to_string())?);
+        self.loaded_texture_paths.insert(path.to_string(), resource::Texture {
+            width: surface.width(),
+            height: surface.height(),
+            ..tex
+        });
+        Ok(())
+    }
+
+    pub(crate) fn record_modified(&mut self, path: &str) {
+        if let Some(time) = file_modified(path) {
+            self.modified.insert(path.to_string(), time);
+        }
+    }
+
 /// draw a `GameObject` to the canvas
-    pub fn draw(&mut self, canvas : &mut Canvas<Window>, tex_draw: TextureDraw) -> Result<(), String> {
-        self.textures[tex_draw.tex.id].set_color_mod(
+    pub fn draw<R: RenderTarget>(&mut self, canvas : &mut Canvas<R>, mut tex_draw: TextureDraw) -> Result<(), String> {
+        if !matches!(self.textures.get(tex_draw.tex.id), Some(Some(_))) {
+            // freed, so draw all of the placeholder instead
+            let fallback = self.fallback()?;
+            tex_draw.tex = fallback;
+            tex_draw.tex_rect = Rect::new(0.0, 0.0, fallback.width as f64, fallback.height as f64);
+        }
+        let texture = self.textures[tex_draw.tex.id].as_mut()
+            .ok_or_else(|| format!("texture {} was freed", tex_draw.tex.id))?;
+        texture.set_color_mod(
             tex_draw.colour.r,
             tex_draw.colour.g,
             tex_draw.colour.b
         );
-        self.textures[tex_draw.tex.id].set_alpha_mod(tex_draw.colour.a);
-        canvas.copy(
-            &self.textures[tex_draw.tex.id],
-            tex_draw.tex_rect.to_sdl_rect(),
-            tex_draw.draw_rect.to_sdl_rect()
+        texture.set_alpha_mod(tex_draw.colour.a);
+        let (flip_angle, flip_h, flip_v) = tex_draw.flip.to_sdl();
+        let angle = tex_draw.angle + flip_angle;
+        // the texture rect is relative to the image, which may be part of an atlas page
+        let mut tex_rect = tex_draw.tex_rect;
+        tex_rect.x += tex_draw.tex.x as f64;
+        tex_rect.y += tex_draw.tex.y as f64;
+        if angle == 0.0 && !flip_h && !flip_v {
+            return canvas.copy(
+                texture,
+                tex_rect.to_sdl_rect(),
+                tex_draw.draw_rect.to_sdl_rect()
+            );
+        }
+        canvas.copy_ex(
+            texture,
+            tex_rect.to_sdl_rect(),
+            tex_draw.draw_rect.to_sdl_rect(),
+            angle,
+            tex_draw.origin.map(|o| sdl2::rect::Point::new(o.x as i32, o.y as i32)),
+            flip_h,
+            flip_v,
         )
     }
 
-    pub fn draw_rect(&self, canvas : &mut Canvas<Window>, rect : &geometry::Rect, colour : &geometry::Rect) -> Result<(), String> {
+    pub fn draw_rect<R: RenderTarget>(&self, canvas : &mut Canvas<R>, rect : &geometry::Rect, colour : &geometry::Rect) -> Result<(), String> {
         canvas.set_draw_color(Color::RGBA(colour.x as u8, colour.y as u8, colour.w as u8, colour.h as u8));
         canvas.fill_rect(rect.to_sdl_rect())?;
         Ok(())
     }
 }
 
+/// an image file as a surface, every frame of a `.aseprite` file is put in one sheet
+fn load_surface(path: &Path) -> Result<Surface<'static>, String> {
+    if path.extension().map(|e| e == "aseprite").unwrap_or(false) {
+        return Ok(aseprite::Aseprite::load(path)?.sheet(None)?.0);
+    }
+    Surface::from_file(path)
+}
+
+/// magenta and black squares, for when there is no fallback image
+fn checkerboard() -> Result<Surface<'static>, String> {
+    let mut surface = Surface::new(16, 16, sdl2::pixels::PixelFormatEnum::RGBA8888)?;
+    surface.fill_rect(None, Color::RGB(255, 0, 255))?;
+    surface.fill_rect(sdl2::rect::Rect::new(0, 0, 8, 8), Color::RGB(0, 0, 0))?;
+    surface.fill_rect(sdl2::rect::Rect::new(8, 8, 8, 8), Color::RGB(0, 0, 0))?;
+    Ok(surface)
+}
+
+fn file_modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
 /// can be returned by `FontManager`, stores an sdl2 texture and a rect for drawing to a canvas
 pub struct TextDraw<'a> {
     pub tex  : sdl2::render::Texture<'a>,
     pub rect : sdl2::rect::Rect,
 }
 
+pub use tiled::{TextHorizontalAlign, TextVerticalAlign};
+
+/// how `FontManager::layout` fits text into a rect
+#[derive(Clone, Copy)]
+pub struct TextStyle {
+    /// the height of each line
+    pub height : f64,
+    /// break lines at spaces when they would be wider than the rect
+    pub wrap   : bool,
+    pub h_align: TextHorizontalAlign,
+    pub v_align: TextVerticalAlign,
+}
+
+impl TextStyle {
+    /// a single line from the top left of the rect
+    pub fn new(height: f64) -> Self {
+        TextStyle {
+            height,
+            wrap: false,
+            h_align: TextHorizontalAlign::Left,
+            v_align: TextVerticalAlign::Top,
+        }
+    }
+}
+
+/// a piece of text placed by `FontManager::layout`
+pub struct TextRun {
+    /// where the run's text is in the layout, see `TextLayout::text`
+    pub text : std::ops::Range<usize>,
+    pub pos  : Vec2,
+    pub width: f64,
+}
+
+/// text split into lines and aligned within a rect.
+/// Laying out into the same `TextLayout` again reuses its buffers
+pub struct TextLayout {
+    pub runs   : Vec<TextRun>,
+    /// the height of each line
+    pub height : f64,
+    /// the space taken up by the laid out text
+    pub size   : Vec2,
+    /// the text of every run
+    buffer : String,
+    /// the words of the line being laid out, as their position in the source text and their width
+    words  : Vec<(usize, usize, f64)>,
+}
+
+impl Default for TextLayout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextLayout {
+    pub fn new() -> Self {
+        TextLayout {
+            runs: Vec::new(),
+            height: 0.0,
+            size: Vec2::new(0.0, 0.0),
+            buffer: String::new(),
+            words: Vec::new(),
+        }
+    }
+
+    pub fn text(&self, run: &TextRun) -> &str {
+        &self.buffer[run.text.clone()]
+    }
+
+    fn clear(&mut self, height: f64) {
+        self.runs.clear();
+        self.buffer.clear();
+        self.words.clear();
+        self.height = height;
+        self.size = Vec2::new(0.0, 0.0);
+    }
+
+    /// turn the current words into runs on a line below the previous ones
+    fn end_line(&mut self, text: &str, width: f64, last: bool, rect: &Rect, style: &TextStyle) {
+        let y = self.size.y;
+        self.size.x = self.size.x.max(width);
+        self.size.y += self.height;
+        if self.words.is_empty() {
+            return;
+        }
+        if style.h_align == TextHorizontalAlign::Justify && !last && self.words.len() > 1 {
+            let words_width : f64 = self.words.iter().map(|w| w.2).sum();
+            let gap = (rect.w - words_width) / (self.words.len() - 1) as f64;
+            let mut x = rect.x;
+            for &(start, end, w) in self.words.iter() {
+                let run_start = self.buffer.len();
+                self.buffer.push_str(&text[start..end]);
+                self.runs.push(TextRun { text: run_start..self.buffer.len(), pos: Vec2::new(x, y), width: w });
+                x += w + gap;
+            }
+        } else {
+            let x = rect.x + match style.h_align {
+                TextHorizontalAlign::Left | TextHorizontalAlign::Justify => 0.0,
+                TextHorizontalAlign::Center => (rect.w - width) / 2.0,
+                TextHorizontalAlign::Right => rect.w - width,
+            };
+            let run_start = self.buffer.len();
+            for (i, &(start, end, _)) in self.words.iter().enumerate() {
+                if i > 0 {
+                    self.buffer.push(' ');
+                }
+                self.buffer.push_str(&text[start..end]);
+            }
+            self.runs.push(TextRun { text: run_start..self.buffer.len(), pos: Vec2::new(x, y), width });
+        }
+        self.words.clear();
+    }
+}
+
 const FONT_LOAD_SIZE : u16 = 128;
 
-/// Stores 'sdl2::ttf::Font' and returns textures or draws them
+/// a font loaded by `FontManager`, either scalable or a pixel font
+pub enum FontType<'a> {
+    Ttf(ttf::Font<'a, 'static>),
+    Bitmap(BitmapFont<'a>),
+}
+
+/// Stores 'sdl2::ttf::Font's and bitmap fonts and returns textures or draws them
 pub struct FontManager<'a, T> {
     texture_creator : &'a TextureCreator<T>,
     ttf_context: &'a ttf::Sdl2TtfContext,
     loaded_font_paths : HashMap<String, usize>,
-    pub fonts : Vec<ttf::Font<'a, 'static>>,
+    pub fonts : Vec<FontType<'a>>,
+    font_paths : Vec<String>,
+    sized_fonts : HashMap<(usize, u32), ttf::Font<'a, 'static>>,
+    glyph_cache : GlyphCache<'a>,
+    fallback_font : Option<PathBuf>,
+    layout : TextLayout,
 }
 
 impl<'a, T> FontManager<'a, T> {
@@ -221,20 +653,38 @@ impl<'a, T> FontManager<'a, T> {
             ttf_context,
             loaded_font_paths: HashMap::new(),
             fonts : Vec::new(),
+            font_paths : Vec::new(),
+            sized_fonts : HashMap::new(),
+            glyph_cache : GlyphCache::new(),
+            fallback_font: None,
+            layout: TextLayout::new(),
         })
     }
 
+    /// a font to use when one fails to load, it is only loaded if it is needed
+    pub fn set_fallback_font(&mut self, path: &Path) {
+        self.fallback_font = Some(path.to_path_buf());
+    }
+
+    /// load a ttf font, or a bitmap font if the path is a BMFont `.fnt` file.
+    /// If the font can't be loaded the fallback font is used, or else the first font that was loaded
     pub fn load_font(&mut self, path : &Path) -> Result<resource::Font, String>{
         let path_string = path.to_string_lossy().to_string();
         let font_index = match self.loaded_font_paths.contains_key(&path_string) {
             true => self.loaded_font_paths[&path_string],
             false => {
-                self.fonts.push(
-                    match self.ttf_context.load_font(path, FONT_LOAD_SIZE) {
-                        Ok(s) => s,
-                        Err(e) => { return Err(e.to_string()); }
-                    }
-                );
+                let bitmap = path.extension().is_some_and(|e| e == "fnt");
+                let font = if bitmap {
+                    BitmapFont::load(path, self.texture_creator).map(FontType::Bitmap)
+                } else {
+                    self.ttf_context.load_font(path, FONT_LOAD_SIZE).map(FontType::Ttf)
+                };
+                let font = match font {
+                    Ok(f) => f,
+                    Err(e) => return self.use_fallback_font(path, e),
+                };
+                self.fonts.push(font);
+                self.font_paths.push(path_string.clone());
                 self.loaded_font_paths.insert(path_string, self.fonts.len() - 1);
                 self.fonts.len() - 1
             }
@@ -244,14 +694,33 @@ impl<'a, T> FontManager<'a, T> {
             id: font_index,
         })
     }
-    /// return a `TextDraw` that has a corrected `rect.width` based on the supplied height and the rendered font
+    fn use_fallback_font(&mut self, path: &Path, error: String) -> Result<resource::Font, String> {
+        println!("failed to load font {}: {}", path.to_string_lossy(), error);
+        if let Some(fallback) = self.fallback_font.clone() {
+            if fallback != path {
+                return self.load_font(&fallback);
+            }
+        }
+        if self.fonts.is_empty() {
+            return Err(error);
+        }
+        println!("using the first font loaded instead");
+        Ok(resource::Font { id: 0 })
+    }
+
+    /// return a `TextDraw` that has a corrected `rect.width` based on the supplied height and the rendered font,
+    /// only ttf fonts can be rendered to their own texture
     pub fn get_draw(&self, font: &resource::Font, text: &str, height : u32, colour : Color) -> Result<TextDraw, String> {
         self.get_draw_at_vec2(font, text, height, Vec2::new(0.0, 0.0), colour)
     }
 
     pub fn get_draw_at_vec2(&self, font: &resource::Font, text: &str, height : u32, pos: Vec2, colour: Color) -> Result<TextDraw, String> {
         if text.len() == 0 { Err("text length should be greater than 0")?; }
-        let surface = match self.fonts[font.id]
+        let ttf_font = match &self.fonts[font.id] {
+            FontType::Ttf(f) => f,
+            FontType::Bitmap(_) => return Err(String::from("bitmap fonts can't be rendered to a TextDraw")),
+        };
+        let surface = match ttf_font
             .render(text)
             .blended(colour) {
                 Ok(s) => s,
@@ -275,12 +744,311 @@ impl<'a, T> FontManager<'a, T> {
         })
     }
 
-    /// draws the supplied text to the canvas in the supplied font at the given height and position
-    pub fn draw(&self, canvas : &mut Canvas<Window>, font : &resource::Font, text: &str, height : u32, pos : Vec2, colour : Color) -> Result<(), String> {
-        if text.len() == 0 { return Ok(()); }
-        let mut tex_draw = self.get_draw(font, text, height, colour)?;
-        tex_draw.rect.x = pos.x as i32;
-        tex_draw.rect.y = pos.y as i32;
-        canvas.copy(&tex_draw.tex, None, tex_draw.rect)
+    /// the width the supplied text would have when drawn at the given height.
+    /// ttf text is measured from the cached glyphs, so it matches what `draw` draws
+    pub fn text_width(&mut self, font: &resource::Font, text: &str, height: f64) -> Result<f64, String> {
+        if text.is_empty() { return Ok(0.0); }
+        if let FontType::Bitmap(f) = &self.fonts[font.id] {
+            return Ok(f.text_width(text, height));
+        }
+        let height = height as u32;
+        if height == 0 { return Ok(0.0); }
+        self.load_sized_font(font, height)?;
+        let sized = GlyphFont { id: font.id, font: &self.sized_fonts[&(font.id, height)], height };
+        Ok(self.glyph_cache.width(self.texture_creator, sized, text)? as f64)
+    }
+
+    /// break text into lines at newlines, and at spaces if `style.wrap` is set and a line would be wider than the rect,
+    /// then align the lines within the rect. The last line of a paragraph is not justified.
+    pub fn layout(&mut self, font: &resource::Font, text: &str, rect: &Rect, style: &TextStyle) -> Result<TextLayout, String> {
+        let mut layout = TextLayout::new();
+        self.layout_into(font, text, rect, style, &mut layout)?;
+        Ok(layout)
+    }
+
+    /// `layout` into an existing `TextLayout`, reusing its buffers
+    pub fn layout_into(&mut self, font: &resource::Font, text: &str, rect: &Rect, style: &TextStyle, layout: &mut TextLayout) -> Result<(), String> {
+        layout.clear(style.height);
+        let space = self.text_width(font, " ", style.height)?;
+        for paragraph in text.split('\n') {
+            let mut width = 0.0;
+            for word in paragraph.split_whitespace() {
+                let w = self.text_width(font, word, style.height)?;
+                let new_width = if layout.words.is_empty() { w } else { width + space + w };
+                if style.wrap && !layout.words.is_empty() && new_width > rect.w {
+                    layout.end_line(text, width, false, rect, style);
+                    width = w;
+                } else {
+                    width = new_width;
+                }
+                // words are slices of `text`, so their offset gives their position in it
+                let start = word.as_ptr() as usize - text.as_ptr() as usize;
+                layout.words.push((start, start + word.len(), w));
+            }
+            layout.end_line(text, width, true, rect, style);
+        }
+
+        let offset = rect.y + match style.v_align {
+            TextVerticalAlign::Top => 0.0,
+            TextVerticalAlign::Center => (rect.h - layout.size.y) / 2.0,
+            TextVerticalAlign::Bottom => rect.h - layout.size.y,
+        };
+        for run in layout.runs.iter_mut() {
+            run.pos.y += offset;
+        }
+        Ok(())
+    }
+
+    pub fn draw_layout<R: RenderTarget>(&mut self, canvas : &mut Canvas<R>, font : &resource::Font, layout: &TextLayout, colour : Color) -> Result<(), String> {
+        for run in layout.runs.iter() {
+            self.draw(canvas, font, layout.text(run), layout.height as u32, run.pos, colour)?;
+        }
+        Ok(())
+    }
+
+    /// lay out and draw text inside a rect, returns the size of the drawn text
+    pub fn draw_in_rect<R: RenderTarget>(&mut self, canvas : &mut Canvas<R>, font : &resource::Font, text: &str, rect: &Rect,
+                        style: &TextStyle, colour : Color) -> Result<Vec2, String> {
+        // lay out into the kept layout so its buffers are reused every call
+        let mut layout = std::mem::take(&mut self.layout);
+        let result = self.layout_into(font, text, rect, style, &mut layout)
+                         .and_then(|_| self.draw_layout(canvas, font, &layout, colour));
+        let size = layout.size;
+        self.layout = layout;
+        result.map(|_| size)
+    }
+
+    /// draws the supplied text to the canvas in the supplied font at the given height and position,
+    /// glyphs are cached so drawing the same text again does not create any textures.
+    /// bitmap fonts are drawn at the largest whole number scale that fits in the height
+    pub fn draw<R: RenderTarget>(&mut self, canvas : &mut Canvas<R>, font : &resource::Font, text: &str, height : u32, pos : Vec2, colour : Color) -> Result<(), String> {
+        if text.is_empty() || height == 0 { return Ok(()); }
+        if let FontType::Bitmap(f) = &mut self.fonts[font.id] {
+            return f.draw(canvas, text, height, pos.x as i32, pos.y as i32, colour);
+        }
+        self.load_sized_font(font, height)?;
+        let sized = GlyphFont { id: font.id, font: &self.sized_fonts[&(font.id, height)], height };
+        self.glyph_cache.draw(canvas, self.texture_creator, sized, text, (pos.x as i32, pos.y as i32), colour)
+    }
+
+    /// load a ttf font at a point size with a line height close to `height`, if it isn't already
+    fn load_sized_font(&mut self, font: &resource::Font, height: u32) -> Result<(), String> {
+        let key = (font.id, height);
+        if !self.sized_fonts.contains_key(&key) {
+            let line_height = match &self.fonts[font.id] {
+                FontType::Ttf(f) => f.height(),
+                FontType::Bitmap(_) => return Err(String::from("bitmap fonts aren't drawn from the glyph cache")),
+            };
+            let size = (height as f64 * FONT_LOAD_SIZE as f64 / line_height as f64).round().max(1.0);
+            let sized = self.ttf_context.load_font(&self.font_paths[font.id], size as u16)?;
+            self.sized_fonts.insert(key, sized);
+        }
+        Ok(())
+    }
+
+    /// how many glyphs have been rasterized, drawing text that was drawn before shouldn't add any
+    pub fn cache_stats(&self) -> GlyphCacheStats {
+        self.glyph_cache.stats()
+    }
+}
+
+/// a file in the temp dir for tests to write to, unique to the test run
+#[cfg(test)]
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("hex_{}_{}", std::process::id(), name))
+}
+
+#[cfg(test)]
+mod texture_tests {
+    use super::*;
+    use crate::render::Headless;
+
+    fn surface(colour: Color) -> Surface<'static> {
+        let mut surface = Surface::new(4, 4, sdl2::pixels::PixelFormatEnum::RGBA8888).unwrap();
+        surface.fill_rect(None, colour).unwrap();
+        surface
+    }
+
+    #[test]
+    fn test_scopes() {
+        let headless = Headless::new(16, 16).unwrap();
+        let texture_creator = headless.texture_creator();
+        let mut tm = TextureManager::new(&texture_creator);
+        let a = tm.add_surface("a", &surface(Color::RGB(255, 0, 0))).unwrap();
+
+        tm.begin_scope();
+        let b = tm.add_surface("b", &surface(Color::RGB(0, 255, 0))).unwrap();
+        // already loaded, so it outlives the scope
+        assert!(tm.add_surface("a", &surface(Color::RGB(255, 0, 0))).unwrap().id == a.id);
+        tm.begin_scope();
+        tm.add_surface("c", &surface(Color::RGB(0, 0, 255))).unwrap();
+        assert!(tm.loaded() == 3);
+        tm.end_scope();
+        assert!(tm.loaded() == 2);
+        assert!(tm.add_surface("b", &surface(Color::RGB(0, 255, 0))).unwrap().id == b.id);
+        tm.end_scope();
+        assert!(tm.loaded() == 1);
+        assert!(tm.add_surface("a", &surface(Color::RGB(255, 0, 0))).unwrap().id == a.id);
+        // b was forgotten so it is made again
+        assert!(tm.add_surface("b", &surface(Color::RGB(0, 255, 0))).unwrap().id != b.id);
+        assert!(tm.loaded() == 2);
+
+        // an unmatched end does nothing
+        tm.end_scope();
+        assert!(tm.loaded() == 2);
+        tm.free(Path::new("a"));
+        assert!(tm.loaded() == 1);
+    }
+
+    /// whether a pixel of a 16x16 canvas matches the built in checkerboard
+    fn checkerboard_at(headless: &Headless, x: usize, y: usize) -> bool {
+        let pixels = headless.pixels().unwrap();
+        let i = (y * 16 + x) * 4;
+        let black = (x < 8) == (y < 8);
+        let expected = if black { [0, 0, 0] } else { [255, 0, 255] };
+        pixels[i..i + 3] == expected
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let headless = Headless::new(16, 16).unwrap();
+        let texture_creator = headless.texture_creator();
+        let mut tm = TextureManager::new(&texture_creator);
+        tm.set_fallback(Path::new("textures/missing_fallback.png"));
+        let missing = tm.load(Path::new("textures/missing.png")).unwrap();
+        assert!(missing.id == tm.fallback().unwrap().id);
+        assert!(missing.width == 16 && missing.height == 16);
+        assert!(tm.load(Path::new("textures/also_missing.png")).unwrap().id == missing.id);
+        assert!(tm.loaded() == 1);
+    }
+
+    #[test]
+    fn test_draw_freed() {
+        let mut headless = Headless::new(16, 16).unwrap();
+        let texture_creator = headless.texture_creator();
+        let mut tm = TextureManager::new(&texture_creator);
+        tm.set_fallback(Path::new("textures/missing_fallback.png"));
+        let tex = tm.add_surface("red", &surface(Color::RGB(255, 0, 0))).unwrap();
+        tm.free(Path::new("red"));
+        let draw = TextureDraw::new(tex, Rect::new(0.0, 0.0, 16.0, 16.0), Rect::new(0.0, 0.0, 4.0, 4.0), Colour::white());
+        tm.draw(&mut headless.canvas, draw).unwrap();
+        for (x, y) in [(0, 0), (12, 2), (3, 12), (12, 12)] {
+            assert!(checkerboard_at(&headless, x, y));
+        }
+    }
+}
+
+#[cfg(test)]
+mod font_tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn test_layout() {
+        let ttf = ttf::init().unwrap();
+        let headless = render::Headless::new(8, 8).unwrap();
+        let texture_creator = headless.texture_creator();
+        let mut fm = FontManager::new(&ttf, &texture_creator).unwrap();
+        let font = fm.load_font(Path::new("textures/VT323-Regular.ttf")).unwrap();
+        // a monospace font, so widths are a whole number of characters
+        let c = fm.text_width(&font, "a", 10.0).unwrap();
+        let texts = |layout: &TextLayout| layout.runs.iter().map(|r| layout.text(r).to_string()).collect::<Vec<String>>();
+
+        let rect = Rect::new(3.0, 4.0, c * 5.5, 100.0);
+        let mut style = TextStyle::new(10.0);
+        let layout = fm.layout(&font, "aa bb cc\ndd", &rect, &style).unwrap();
+        assert!(texts(&layout) == vec!["aa bb cc", "dd"]);
+        assert!(close(layout.runs[1].pos.x, 3.0) && close(layout.runs[1].pos.y, 14.0));
+        assert!(close(layout.size.x, c * 8.0) && close(layout.size.y, 20.0));
+
+        style.wrap = true;
+        let layout = fm.layout(&font, "aa bb cc\ndd", &rect, &style).unwrap();
+        assert!(texts(&layout) == vec!["aa bb", "cc", "dd"]);
+        assert!(close(layout.runs[2].pos.y, 24.0));
+        assert!(close(layout.size.x, c * 5.0));
+
+        style.h_align = TextHorizontalAlign::Right;
+        style.v_align = TextVerticalAlign::Bottom;
+        let layout = fm.layout(&font, "aa bb cc", &rect, &style).unwrap();
+        assert!(close(layout.runs[1].pos.x, 3.0 + c * 3.5));
+        assert!(close(layout.runs[0].pos.y, 84.0) && close(layout.runs[1].pos.y, 94.0));
+
+        style.h_align = TextHorizontalAlign::Center;
+        style.v_align = TextVerticalAlign::Center;
+        let layout = fm.layout(&font, "aa bb cc", &rect, &style).unwrap();
+        assert!(close(layout.runs[1].pos.x, 3.0 + c * 1.75));
+        assert!(close(layout.runs[0].pos.y, 44.0));
+    }
+
+    #[test]
+    fn test_glyph_cache() {
+        let ttf = ttf::init().unwrap();
+        let mut headless = render::Headless::new(64, 32).unwrap();
+        let texture_creator = headless.texture_creator();
+        let mut fm = FontManager::new(&ttf, &texture_creator).unwrap();
+        let font = fm.load_font(Path::new("textures/VT323-Regular.ttf")).unwrap();
+        let white = Color::RGB(255, 255, 255);
+        let rect = Rect::new(0.0, 0.0, 64.0, 32.0);
+        let style = TextStyle { wrap: true, ..TextStyle::new(10.0) };
+
+        fm.draw_in_rect(&mut headless.canvas, &font, "hex hex\nscore", &rect, &style, white).unwrap();
+        let first = fm.cache_stats();
+        // each different character is rasterized once
+        assert!(first.misses == 8 && first.glyphs == 8 && first.pages == 1);
+
+        fm.draw_in_rect(&mut headless.canvas, &font, "hex hex\nscore", &rect, &style, white).unwrap();
+        fm.draw(&mut headless.canvas, &font, "core", 10, Vec2::new(0.0, 0.0), white).unwrap();
+        let second = fm.cache_stats();
+        assert!(second.misses == first.misses && second.glyphs == first.glyphs && second.pages == first.pages);
+        assert!(second.hits > first.hits);
+
+        // another height is another set of glyphs
+        fm.draw(&mut headless.canvas, &font, "hex", 20, Vec2::new(0.0, 0.0), white).unwrap();
+        assert!(fm.cache_stats().misses == first.misses + 3);
+    }
+
+    #[test]
+    fn test_glyph_widths() {
+        let ttf = ttf::init().unwrap();
+        let mut headless = render::Headless::new(64, 32).unwrap();
+        let texture_creator = headless.texture_creator();
+        let mut fm = FontManager::new(&ttf, &texture_creator).unwrap();
+        let font = fm.load_font(Path::new("textures/FiraCode-Regular.ttf")).unwrap();
+        // widths are whole glyph widths added up, the same as they are drawn
+        let width = fm.text_width(&font, "hex", 13.0).unwrap();
+        let glyphs : f64 = "hex".chars().map(|c| fm.text_width(&font, &c.to_string(), 13.0).unwrap()).sum();
+        assert!(width == glyphs && width.fract() == 0.0);
+        assert!(fm.text_width(&font, "hex", 13.7).unwrap() == width);
+
+        // a character that can't be rendered is skipped instead of failing the draw
+        let stats = fm.cache_stats();
+        fm.draw(&mut headless.canvas, &font, "h\0x", 13, Vec2::new(0.0, 0.0), Color::RGB(255, 255, 255)).unwrap();
+        assert!(fm.cache_stats().glyphs == stats.glyphs && fm.cache_stats().misses == stats.misses + 1);
+        assert!(fm.text_width(&font, "h\0x", 13.0).unwrap() == fm.text_width(&font, "hx", 13.0).unwrap());
+    }
+
+    #[test]
+    fn test_justify() {
+        let ttf = ttf::init().unwrap();
+        let headless = render::Headless::new(8, 8).unwrap();
+        let texture_creator = headless.texture_creator();
+        let mut fm = FontManager::new(&ttf, &texture_creator).unwrap();
+        let font = fm.load_font(Path::new("textures/VT323-Regular.ttf")).unwrap();
+        let c = fm.text_width(&font, "a", 10.0).unwrap();
+
+        let rect = Rect::new(0.0, 0.0, c * 8.0, 100.0);
+        let style = TextStyle { wrap: true, h_align: TextHorizontalAlign::Justify, ..TextStyle::new(10.0) };
+        let layout = fm.layout(&font, "aa b cc dd\nee ff", &rect, &style).unwrap();
+        let runs : Vec<(&str, f64)> = layout.runs.iter().map(|r| (layout.text(r), r.pos.x)).collect();
+        // wrapped lines are spread to fill the rect, the last line of each paragraph isn't
+        assert!(runs.len() == 5);
+        assert!(runs[0].0 == "aa" && close(runs[0].1, 0.0));
+        assert!(runs[1].0 == "b" && close(runs[1].1, c * 3.5));
+        assert!(runs[2].0 == "cc" && close(runs[2].1, c * 6.0));
+        assert!(runs[3].0 == "dd" && close(runs[3].1, 0.0));
+        assert!(runs[4].0 == "ee ff" && close(runs[4].1, 0.0));
     }
 }
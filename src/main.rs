@@ -1,19 +1,27 @@
-
 use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
 use sdl2::image;
-use sdl2::video::Window;
-use sdl2::render::Canvas;
+use sdl2::video::{Window, WindowContext, FullscreenType};
+use sdl2::render::{Canvas, Texture, BlendMode};
+use sdl2::pixels::PixelFormatEnum;
 
 use geometry::Vec2;
 use gudevJam12::{
     TextureManager,
     FontManager,
+    GameObject,
+    resource,
     map,
-    camera::Camera,
-    input::Input,
-    hex::HexGrid,
+    camera::{Camera, layer},
+    screen::Screen,
+    atlas::AtlasBuilder,
+    input::{Input, Action, Bindings},
+    controller::{self, Controllers},
+    hex::{HexGrid, Palette},
+    highscore::{HighScores, NAME_LENGTH},
+    ui::{Ui, Anchor},
+    scene::{Scene, SceneStack, Transition},
 };
 
 use std::time::Instant;
@@ -22,23 +30,454 @@ use std::path::Path;
 const TARGET_WIDTH : f64 = 240.0;
 const TARGET_HEIGHT : f64 = 160.0;
 
+/// packed into one texture so the board is drawn without switching textures
+const BOARD_TEXTURES : [&str; 13] = [
+    "textures/tile/blank.png",
+    "textures/tile/red.png",
+    "textures/tile/green.png",
+    "textures/tile/blue.png",
+    "textures/tile/yellow.png",
+    "textures/tile/mid.png",
+    "textures/tile/white.png",
+    "textures/tile/symbol/circle.png",
+    "textures/tile/symbol/triangle.png",
+    "textures/tile/symbol/square.png",
+    "textures/tile/symbol/cross.png",
+    "textures/hl1.png",
+    "textures/hl2.png",
+];
+
+const CONTROLS_PATH : &str = "controls.cfg";
+const HIGHSCORES_PATH : &str = "highscores.txt";
+
+/// everything the scenes share
+struct Game<'a> {
+    canvas: Canvas<Window>,
+    /// the game is drawn here at the target resolution, then scaled into the window
+    target: Texture<'a>,
+    screen: Screen,
+    cam: Camera,
+    texture_manager: TextureManager<'a, WindowContext>,
+    font_manager: FontManager<'a, WindowContext>,
+    mono_font: resource::Font,
+    bg: GameObject,
+    hex_grid: HexGrid,
+    palette: Color,
+    highscore: usize,
+    new_hs: bool,
+    bindings: Bindings,
+    /// false if the controls file exists but couldn't be read, so it isn't written over
+    save_bindings: bool,
+    highscores: HighScores,
+    /// false if the high score file exists but couldn't be read, so it isn't written over
+    save_highscores: bool,
+    /// whether sdl should be sending typed text
+    text_input: bool,
+}
+
+impl<'a> Game<'a> {
+    fn draw_cam(&mut self) -> Result<(), String> {
+        draw_camera(&mut self.canvas, &mut self.target, &mut self.texture_manager, &mut self.cam)
+    }
+
+    fn draw_ui(&mut self, ui: &Ui) -> Result<(), String> {
+        let (texture_manager, font_manager, font) = (&self.texture_manager, &mut self.font_manager, &self.mono_font);
+        let mut result = Ok(());
+        self.canvas.with_texture_canvas(&mut self.target, |canvas| {
+            result = ui.draw(canvas, texture_manager, font_manager, font);
+        }).map_err(|e| e.to_string())?;
+        result
+    }
+
+    fn fill_screen(&mut self, colour: Color) -> Result<(), String> {
+        let size = self.screen.get_target_size();
+        let mut result = Ok(());
+        self.canvas.with_texture_canvas(&mut self.target, |canvas| {
+            canvas.set_draw_color(colour);
+            result = canvas.fill_rect(sdl2::rect::Rect::new(0, 0, size.x as u32, size.y as u32));
+        }).map_err(|e| e.to_string())?;
+        result
+    }
+
+    fn clear_target(&mut self) -> Result<(), String> {
+        self.canvas.with_texture_canvas(&mut self.target, |canvas| {
+            canvas.set_draw_color(Color::RGB(0, 0, 0));
+            canvas.clear();
+        }).map_err(|e| e.to_string())
+    }
+
+    /// scale the target into the window, leaving black bars where it doesn't fit
+    fn present(&mut self) -> Result<(), String> {
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        let v = self.screen.viewport();
+        self.canvas.copy(&self.target, None, sdl2::rect::Rect::new(v.x as i32, v.y as i32, v.w as u32, v.h as u32))?;
+        self.canvas.present();
+        Ok(())
+    }
+}
+
+/// draw a camera's queued draws to the target, clipped to the camera's viewport.
+/// Call once for each camera to show several views in one frame
+fn draw_camera<'a>(canvas: &mut Canvas<Window>, target: &mut Texture<'a>, texture_manager: &mut TextureManager<'a, WindowContext>,
+                   cam: &mut Camera) -> Result<(), String> {
+    let v = cam.get_viewport();
+    let mut result = Ok(());
+    canvas.with_texture_canvas(target, |canvas| {
+        canvas.set_clip_rect(sdl2::rect::Rect::new(v.x as i32, v.y as i32, v.w as u32, v.h as u32));
+        for d in cam.drain_draws() {
+            result = texture_manager.draw(canvas, d);
+            if result.is_err() {
+                break;
+            }
+        }
+        canvas.set_clip_rect(None);
+    }).map_err(|e| e.to_string())?;
+    result
+}
+
+/// clearing at least this many tiles at once shakes the screen
+const BIG_CLEAR : usize = 8;
+const TRAUMA_PER_TILE : f64 = 0.05;
+const GAME_OVER_ZOOM : f64 = 1.15;
+
+const MENU_BUTTON : Vec2 = Vec2 { x: 100.0, y: 12.0 };
+
+struct Title {
+    ui: Ui,
+}
+
+impl<'a> Scene<Game<'a>> for Title {
+    fn update(&mut self, game: &mut Game<'a>, _: f64, input: &Input) -> Transition<Game<'a>> {
+        let ui = &mut self.ui;
+        ui.begin(input, &game.cam);
+        ui.centred_label("HEX", ui.anchor(Anchor::Top, Vec2::new(120.0, 40.0), Vec2::new(0.0, 30.0)));
+        if ui.button("START", ui.anchor(Anchor::Bottom, MENU_BUTTON, Vec2::new(0.0, 20.0))) {
+            return Transition::Replace(Box::new(Play::new()));
+        }
+        Transition::None
+    }
+
+    fn draw(&self, game: &mut Game<'a>) -> Result<(), String> {
+        game.cam.add_cam_space(&game.bg);
+        game.draw_cam()?;
+        game.draw_ui(&self.ui)
+    }
+}
+
+struct Play {
+    ui: Ui,
+}
+
+impl Play {
+    fn new() -> Play {
+        Play { ui: Ui::new() }
+    }
+}
+
+impl<'a> Scene<Game<'a>> for Play {
+    fn enter(&mut self, game: &mut Game<'a>) {
+        game.hex_grid.reset();
+        game.hex_grid.set_auto_repeat(game.bindings.repeat_delay, game.bindings.repeat_rate);
+        game.new_hs = false;
+    }
+
+    fn update(&mut self, game: &mut Game<'a>, timer: f64, input: &Input) -> Transition<Game<'a>> {
+        if input.pressed(Action::B) {
+            return Transition::Push(Box::new(Pause { ui: Ui::new() }));
+        }
+        game.hex_grid.update(&timer, input, &game.cam);
+        if game.hex_grid.cleared() >= BIG_CLEAR {
+            game.cam.add_trauma(game.hex_grid.cleared() as f64 * TRAUMA_PER_TILE);
+        }
+
+        let score = game.hex_grid.score();
+        if score < 30 {
+            game.palette = Color::RGBA(255, 255, 255, 255);
+        } else if score < 70 {
+            game.palette = Color::RGBA(150, 100, 220, 160);
+        } else if score < 120 {
+            game.palette = Color::RGBA(255, 100, 0, 160);
+        } else if score < 160 {
+            game.palette = Color::RGBA(155, 255, 100, 170);
+        } else if score < 210 {
+            game.palette = Color::RGBA(100, 200, 255, 200);
+        } else if score < 260 {
+            game.palette = Color::RGBA(255, 255, 100, 160);
+        } else if score < 300 {
+            game.palette = Color::RGBA(200, 170, 255, 100);
+        } else if score < 350 {
+            game.palette = Color::RGBA(200, 90, 200, 200)
+        } else if score < 400 {
+            game.palette = Color::RGBA(255, 90, 60, 230);
+        } else if score < 450 {
+            game.palette = Color::RGBA(255, 90, 60, 240);
+        } else if score < 500 {
+            game.palette = Color::RGBA(255, 40, 40, 250);
+        }
+
+        if score > game.highscore {
+            game.highscore = score;
+            game.new_hs = true;
+        }
+
+        let ui = &mut self.ui;
+        ui.begin(input, &game.cam);
+        ui.label(&format!("score: {}", score), ui.anchor(Anchor::TopLeft, Vec2::new(60.0, 7.0), Vec2::new(15.0, 10.0)));
+        ui.label(&format!("highscore: {}", game.highscore), ui.anchor(Anchor::TopLeft, Vec2::new(60.0, 7.0), Vec2::new(4.0, 18.0)));
+        let bar_height = ui.view_size().y / game.hex_grid.spawn_ratio();
+        ui.panel(ui.anchor(Anchor::BottomRight, Vec2::new(5.0, bar_height), Vec2::new(0.0, 0.0)), Color::RGB(32, 31, 46));
+
+        if game.hex_grid.lost() {
+            return Transition::Push(Box::new(GameOver::new(game)));
+        }
+        Transition::None
+    }
+
+    fn draw(&self, game: &mut Game<'a>) -> Result<(), String> {
+        game.cam.add_cam_space(&game.bg);
+        game.hex_grid.draw(&mut game.cam);
+        game.draw_cam()?;
+        game.draw_ui(&self.ui)?;
+        game.fill_screen(game.palette)
+    }
+}
+
+struct Pause {
+    ui: Ui,
+}
+
+impl<'a> Scene<Game<'a>> for Pause {
+    fn update(&mut self, game: &mut Game<'a>, _: f64, input: &Input) -> Transition<Game<'a>> {
+        if input.pressed(Action::B) {
+            return Transition::Pop;
+        }
+        let ui = &mut self.ui;
+        ui.begin(input, &game.cam);
+        ui.centred_label("PAUSED", ui.anchor(Anchor::Top, Vec2::new(120.0, 40.0), Vec2::new(0.0, 30.0)));
+        if ui.button("RESUME", ui.anchor(Anchor::Bottom, MENU_BUTTON, Vec2::new(0.0, 34.0))) {
+            return Transition::Pop;
+        }
+        if ui.button("SETTINGS", ui.anchor(Anchor::Bottom, MENU_BUTTON, Vec2::new(0.0, 20.0))) {
+            return Transition::Push(Box::new(Settings { ui: Ui::new() }));
+        }
+        Transition::None
+    }
+
+    fn draw(&self, game: &mut Game<'a>) -> Result<(), String> {
+        game.fill_screen(Color::RGBA(10, 10, 10, 200))?;
+        game.draw_ui(&self.ui)
+    }
+
+    fn overlay(&self) -> bool { true }
+}
+
+struct Settings {
+    ui: Ui,
+}
+
+impl<'a> Scene<Game<'a>> for Settings {
+    fn update(&mut self, game: &mut Game<'a>, _: f64, input: &Input) -> Transition<Game<'a>> {
+        if input.pressed(Action::B) {
+            return Transition::Pop;
+        }
+        let mut style = game.hex_grid.tile_style();
+        let palette = match style.palette {
+            Palette::Default => "default",
+            Palette::Deuteranopia => "deuteranopia",
+            Palette::Protanopia => "protanopia",
+            Palette::Tritanopia => "tritanopia",
+        };
+
+        let ui = &mut self.ui;
+        ui.begin(input, &game.cam);
+        ui.centred_label("SETTINGS", ui.anchor(Anchor::Top, Vec2::new(120.0, 30.0), Vec2::new(0.0, 20.0)));
+        let option = Vec2::new(140.0, 12.0);
+        if ui.button(&format!("palette: {}", palette), ui.anchor(Anchor::Centre, option, Vec2::new(0.0, 0.0))) {
+            style.palette = style.palette.next();
+        }
+        ui.toggle("symbols", ui.anchor(Anchor::Centre, option, Vec2::new(0.0, 14.0)), &mut style.symbols);
+        game.hex_grid.set_tile_style(style);
+        if ui.button("CONTROLS", ui.anchor(Anchor::Centre, option, Vec2::new(0.0, 28.0))) {
+            return Transition::Push(Box::new(Controls { ui: Ui::new(), listening: None }));
+        }
+        if ui.button("BACK", ui.anchor(Anchor::Bottom, MENU_BUTTON, Vec2::new(0.0, 20.0))) {
+            return Transition::Pop;
+        }
+        Transition::None
+    }
+
+    fn draw(&self, game: &mut Game<'a>) -> Result<(), String> {
+        game.fill_screen(Color::RGBA(10, 10, 10, 200))?;
+        game.draw_ui(&self.ui)
+    }
+
+    fn overlay(&self) -> bool { true }
+}
+
+/// actions shown on the controls screen
+const REBINDABLE : [Action; 7] = [
+    Action::Up,
+    Action::Down,
+    Action::Left,
+    Action::Right,
+    Action::A,
+    Action::B,
+    Action::Restart,
+];
+
+struct Controls {
+    ui: Ui,
+    /// the action waiting for a button press to bind to it
+    listening: Option<Action>,
+}
+
+impl<'a> Scene<Game<'a>> for Controls {
+    fn update(&mut self, game: &mut Game<'a>, _: f64, input: &Input) -> Transition<Game<'a>> {
+        let ui = &mut self.ui;
+        ui.begin(input, &game.cam);
+        ui.centred_label("CONTROLS", ui.anchor(Anchor::Top, Vec2::new(120.0, 20.0), Vec2::new(0.0, 6.0)));
+
+        if let Some(action) = self.listening {
+            ui.centred_label(&format!("press a button for {}", action.name()),
+                             ui.anchor(Anchor::Centre, Vec2::new(200.0, 10.0), Vec2::new(0.0, 0.0)));
+            if let Some(binding) = input.last_pressed {
+                game.bindings.rebind(action, binding);
+                save_bindings(game);
+                self.listening = None;
+            }
+            return Transition::None;
+        }
+
+        if input.pressed(Action::B) {
+            return Transition::Pop;
+        }
+        let row = Vec2::new(200.0, 9.0);
+        for (i, action) in REBINDABLE.iter().enumerate() {
+            let names : Vec<String> = game.bindings.get(*action).iter().map(|b| b.name()).collect();
+            if ui.button(&format!("{}: {}", action.name(), names.join(" ")),
+                         ui.anchor(Anchor::Top, row, Vec2::new(0.0, 30.0 + 10.0 * i as f64))) {
+                self.listening = Some(*action);
+            }
+        }
+        if ui.button("RESET", ui.anchor(Anchor::Bottom, MENU_BUTTON, Vec2::new(0.0, 20.0))) {
+            game.bindings = Bindings::new();
+            save_bindings(game);
+        }
+        if ui.button("BACK", ui.anchor(Anchor::Bottom, MENU_BUTTON, Vec2::new(0.0, 6.0))) {
+            return Transition::Pop;
+        }
+        Transition::None
+    }
+
+    fn draw(&self, game: &mut Game<'a>) -> Result<(), String> {
+        game.fill_screen(Color::RGBA(10, 10, 10, 200))?;
+        game.draw_ui(&self.ui)
+    }
+
+    fn overlay(&self) -> bool { true }
+
+    fn captures_input(&self) -> bool { self.listening.is_some() }
+}
+
+fn save_bindings(game: &Game) {
+    if !game.save_bindings {
+        println!("not saving controls, {} couldn't be read", CONTROLS_PATH);
+    } else if let Err(e) = game.bindings.save(Path::new(CONTROLS_PATH)) {
+        println!("failed to save controls: {}", e);
+    }
+}
+
+struct GameOver {
+    ui: Ui,
+    /// the name being typed while the score is being added to the high score table
+    name: Option<String>,
+}
+
+impl GameOver {
+    fn new(game: &Game) -> GameOver {
+        GameOver {
+            ui: Ui::new(),
+            name: if game.highscores.qualifies(game.hex_grid.score()) { Some(String::new()) } else { None },
+        }
+    }
+}
+
+impl<'a> Scene<Game<'a>> for GameOver {
+    fn enter(&mut self, game: &mut Game<'a>) {
+        let view = game.cam.get_view_size();
+        game.cam.zoom_to(GAME_OVER_ZOOM, Vec2::new(view.x / 2.0, view.y / 2.0));
+    }
+
+    fn exit(&mut self, game: &mut Game<'a>) {
+        let view = game.cam.get_view_size();
+        game.cam.zoom_to(1.0, Vec2::new(view.x / 2.0, view.y / 2.0));
+        game.hex_grid.reset();
+        game.new_hs = false;
+        game.text_input = false;
+    }
+
+    fn update(&mut self, game: &mut Game<'a>, _: f64, input: &Input) -> Transition<Game<'a>> {
+        let ui = &mut self.ui;
+        ui.begin(input, &game.cam);
+        ui.centred_label("GAME OVER", ui.anchor(Anchor::Top, Vec2::new(160.0, 30.0), Vec2::new(0.0, 8.0)));
+        ui.centred_label(&format!("FINAL SCORE: {}", game.hex_grid.score()),
+                         ui.anchor(Anchor::Top, Vec2::new(160.0, 12.0), Vec2::new(0.0, 40.0)));
+        if game.new_hs {
+            ui.centred_label("NEW HIGH SCORE!", ui.anchor(Anchor::Top, Vec2::new(160.0, 10.0), Vec2::new(0.0, 54.0)));
+        }
+
+        game.text_input = self.name.is_some();
+        if let Some(name) = &mut self.name {
+            ui.centred_label("enter your name", ui.anchor(Anchor::Top, Vec2::new(160.0, 9.0), Vec2::new(0.0, 72.0)));
+            if ui.text_field(ui.anchor(Anchor::Top, Vec2::new(80.0, 12.0), Vec2::new(0.0, 84.0)), name, NAME_LENGTH) {
+                game.highscores.insert(name, game.hex_grid.score());
+                if !game.save_highscores {
+                    println!("not saving high scores, {} couldn't be read", HIGHSCORES_PATH);
+                } else if let Err(e) = game.highscores.save(Path::new(HIGHSCORES_PATH)) {
+                    println!("failed to save high scores: {}", e);
+                }
+                self.name = None;
+            }
+            return Transition::None;
+        }
+
+        for (i, (name, score)) in game.highscores.entries().iter().enumerate() {
+            ui.label(&format!("{}. {:<10} {}", i + 1, name, score),
+                     ui.anchor(Anchor::Top, Vec2::new(80.0, 9.0), Vec2::new(0.0, 68.0 + 10.0 * i as f64)));
+        }
+        if ui.button("RETRY", ui.anchor(Anchor::Bottom, MENU_BUTTON, Vec2::new(0.0, 8.0))) {
+            return Transition::Pop;
+        }
+        Transition::None
+    }
+
+    fn draw(&self, game: &mut Game<'a>) -> Result<(), String> {
+        game.fill_screen(Color::RGBA(10, 10, 10, 200))?;
+        game.draw_ui(&self.ui)
+    }
+
+    fn overlay(&self) -> bool { true }
+}
+
 pub fn main() -> Result<(), String> {
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
     let _image_context = image::init(image::InitFlag::PNG);
 
-    let mut cam = Camera::new(
-        geometry::Rect::new(0.0, 0.0, TARGET_WIDTH, TARGET_HEIGHT),
-        geometry::Vec2::new(TARGET_WIDTH * 4.0, TARGET_HEIGHT * 4.0)
-    );
-    
+    let target_size = Vec2::new(TARGET_WIDTH, TARGET_HEIGHT);
+    // the camera draws to the target, which is then scaled up to the window
+    let cam = Camera::new(geometry::Rect::new(0.0, 0.0, TARGET_WIDTH, TARGET_HEIGHT), target_size);
+    let screen = Screen::new(target_size, Vec2::new(TARGET_WIDTH * 4.0, TARGET_HEIGHT * 4.0));
+
     let window = video_subsystem
         .window(
             "Hex",
-            cam.get_window_size().x as u32,
-            cam.get_window_size().y as u32
+            screen.get_window_size().x as u32,
+            screen.get_window_size().y as u32
         )
         .opengl()
+        .resizable()
         .build()
         .map_err(|e| e.to_string())?;
 
@@ -49,141 +488,121 @@ pub fn main() -> Result<(), String> {
         .map_err(|e| e.to_string())?;
 
     let texture_creator = canvas.texture_creator();
+    let mut target = texture_creator
+        .create_texture_target(PixelFormatEnum::RGBA8888, TARGET_WIDTH as u32, TARGET_HEIGHT as u32)
+        .map_err(|e| e.to_string())?;
+    target.set_blend_mode(BlendMode::None);
     let mut texture_manager = TextureManager::new(&texture_creator);
+    // pick up edits to the art while the game runs in debug builds
+    texture_manager.watch(cfg!(debug_assertions));
     let ttf_context = sdl2::ttf::init().map_err(|e| e.to_string())?;
     let mut font_manager = FontManager::new(&ttf_context, &texture_creator)?;
 
+    font_manager.set_fallback_font(Path::new("textures/FiraCode-Regular.ttf"));
     let mono_font = font_manager.load_font(Path::new("textures/VT323-Regular.ttf"))?;
 
-    let test = gudevJam12::GameObject::new_from_tex(texture_manager.load(Path::new("textures/bg.png"))?);
+    let mut bg = GameObject::new_from_tex(texture_manager.load(Path::new("textures/bg.aseprite"))?);
+    bg.layer = layer::BACKGROUND;
+
+    let mut board_atlas = AtlasBuilder::new();
+    for path in BOARD_TEXTURES.iter() {
+        board_atlas.add(Path::new(path))?;
+    }
+    texture_manager.load_atlas(&board_atlas.build()?)?;
+    let hex_grid = HexGrid::new(&mut texture_manager)?;
 
-    let mut hex_grid = HexGrid::new(&mut texture_manager)?;
-    
     canvas.set_blend_mode(sdl2::render::BlendMode::Mul);
 
-    let mut palette = Color::RGBA(0, 0, 0, 0);
+    let highscores_path = Path::new(HIGHSCORES_PATH);
+    let (highscores, save_highscores) = match HighScores::load(highscores_path) {
+        Ok(h) => (h, true),
+        Err(_) if !highscores_path.exists() => (HighScores::new(), true),
+        Err(e) => {
+            println!("failed to load high scores from {}: {}", HIGHSCORES_PATH, e);
+            (HighScores::new(), false)
+        },
+    };
+
+    let controls_path = Path::new(CONTROLS_PATH);
+    let (bindings, save_bindings) = match Bindings::load(controls_path) {
+        Ok(b) => (b, true),
+        Err(_) if !controls_path.exists() => (Bindings::new(), true),
+        Err(e) => {
+            println!("failed to load controls from {}: {}", CONTROLS_PATH, e);
+            (Bindings::new(), false)
+        },
+    };
 
-    let mut highscore = 0;
-    let mut new_hs = false;
+    let mut game = Game {
+        canvas,
+        target,
+        screen,
+        cam,
+        texture_manager,
+        font_manager,
+        mono_font,
+        bg,
+        hex_grid,
+        palette: Color::RGBA(0, 0, 0, 0),
+        highscore: 0,
+        new_hs: false,
+        highscores,
+        save_highscores,
+        text_input: false,
+        bindings,
+        save_bindings,
+    };
+
+    game.highscore = game.highscores.best();
+    let text_input = video_subsystem.text_input();
+    text_input.stop();
+
+    let mut scenes = SceneStack::new(|| Box::new(Play::new()));
+    scenes.push(&mut game, Box::new(Title { ui: Ui::new() }));
+
+    let mut controllers = Controllers::new(sdl_context.game_controller()?);
 
     let mut event_pump = sdl_context.event_pump()?;
-    let mut input = Input::new();
-    let mut p_inp = input;
+    // one input per player, keyboard and mouse go to the first
+    let mut inputs = vec![Input::new()];
     let mut prev_frame : f64 = 0.0;
     'running: loop {
         let start_time = Instant::now();
+        for input in inputs.iter_mut() {
+            input.advance(prev_frame);
+        }
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } | Event::KeyDown {  keycode: Some(Keycode::Escape), ..} => break 'running,
                 _ => { }
             }
-            input.handle_event(&event);
-            handle_event(&event, &mut canvas, &mut cam)?;
-        }
-        
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
-        canvas.clear();
-        
-        //map.draw(&mut cam);
-        cam.add_cam_space(&test);
-        
-        hex_grid.draw(&mut cam);
-        
-        for d in cam.drain_draws() {
-            texture_manager.draw(&mut canvas, d)?;
-        }
-
-        let cam_x = cam.get_window_size().x / cam.get_view_size().x;
-        font_manager.draw(&mut canvas, &mono_font, &format!("score: {}", hex_grid.score()), (7.0*cam_x) as u32, Vec2::new(15.0*cam_x, 10.0*cam_x), Color::RGB(178, 178, 178))?;
-        font_manager.draw(&mut canvas, &mono_font, &format!("highscore: {}", highscore), (7.0*cam_x) as u32, Vec2::new(4.0*cam_x, 18.0*cam_x), Color::RGB(178, 178, 178))?;
-
-        canvas.set_draw_color(Color::RGB(32, 31, 46));
-        let width = 20;
-        let height = (cam.get_window_size().y / hex_grid.spawn_ratio())as u32;
-        canvas.fill_rect(sdl2::rect::Rect::new(cam.get_window_size().x as i32 - width, (cam.get_window_size().y - height as f64) as i32,
-                                               width as u32, height))?;
-                                               
-        
-        canvas.set_draw_color(palette);
-
-        if hex_grid.lost() {
-            
-            canvas.set_draw_color(Color::RGBA(10, 10, 10, 200));
-        }
-        
-        canvas.fill_rect(sdl2::rect::Rect::new(0, 0, cam.get_window_size().x as u32, cam.get_window_size().y as u32))?;
-
-        if hex_grid.lost() {
-            font_manager.draw(&mut canvas, &mono_font, "GAME OVER",
-                              (40.0*cam_x) as u32,
-                              Vec2::new(45.0*cam_x, 30.0*cam_x),
-                              Color::RGB(200, 200, 200))?;
-
-            font_manager.draw(&mut canvas, &mono_font, &format!("FINAL SCORE: {}", hex_grid.score()),
-                              (20.0*cam_x) as u32,
-                              Vec2::new(57.0*cam_x, 67.0*cam_x),
-                              Color::RGB(200, 200, 200))?;
-
-            if new_hs {
-                 font_manager.draw(&mut canvas, &mono_font, "NEW HIGH SCORE!",
-                              (15.0*cam_x) as u32,
-                              Vec2::new(78.0*cam_x, 110.0*cam_x),
-                              Color::RGB(200, 200, 200))?;
-
+            // find the player first, a removed controller is forgotten by `handle_event`
+            let player = controllers.player(&event);
+            if let Err(e) = controllers.handle_event(&event) {
+                println!("failed to open controller: {}", e);
             }
-
-             font_manager.draw(&mut canvas, &mono_font, "Z TO RETRY",
-                              (10.0*cam_x) as u32,
-                              Vec2::new(100.0*cam_x, 130.0*cam_x),
-                              Color::RGB(200, 200, 200))?;
-            
-        }
-        
-        canvas.present(); 
-
-        if hex_grid.score() < 30 {
-            palette = Color::RGBA(255, 255, 255, 255);
-        } else if hex_grid.score() < 70 {
-            palette = Color::RGBA(150, 100, 220, 160);
-        } else if hex_grid.score() < 120 {
-            palette = Color::RGBA(255, 100, 0, 160);
-        } else if hex_grid.score() < 160 {
-            palette = Color::RGBA(155, 255, 100, 170);
-        } else if hex_grid.score() < 210 {
-            palette = Color::RGBA(100, 200, 255, 200);
-        } else if hex_grid.score() < 260 {
-            palette = Color::RGBA(255, 255, 100, 160);
-        } else if hex_grid.score() < 300 {
-            palette = Color::RGBA(200, 170, 255, 100);
-        } else if hex_grid.score() < 300 {
-            palette = Color::RGBA(255, 90, 100, 150);
-        } else if hex_grid.score() < 350 {
-            palette = Color::RGBA(200, 90, 200, 200)
-        } else if hex_grid.score() < 400 {
-            palette = Color::RGBA(255, 90, 60, 230);
-        } else if hex_grid.score() < 450 {
-            palette = Color::RGBA(255, 90, 60, 240);
-        } else if hex_grid.score() < 500 {
-            palette = Color::RGBA(255, 40, 40, 250);
-        }
-        
-        if hex_grid.score() > highscore {
-            highscore = hex_grid.score();
-            new_hs = true;
-        }
-        
-        if hex_grid.lost() {
-            if input.a && !p_inp.a{
-                hex_grid.reset();
-                new_hs = false;
+            if player.is_some() || !event.is_controller() {
+                controller::player_input(&mut inputs, player)
+                    .handle_event(&game.screen.map_event(&event), &game.bindings);
             }
-        } else {
-            hex_grid.update(&prev_frame, &input);
+            handle_event(&event, &mut game.canvas, &mut game.screen, game.text_input)?;
         }
-              
-        
+
+        game.clear_target()?;
+        scenes.draw(&mut game)?;
+        game.present()?;
+
+        scenes.update(&mut game, prev_frame, &inputs[0]);
+        game.cam.update(prev_frame);
+        game.texture_manager.update(prev_frame);
+        if game.text_input != text_input.is_active() {
+            if game.text_input { text_input.start(); } else { text_input.stop(); }
+        }
+        if scenes.is_empty() {
+            break 'running;
+        }
+
         prev_frame = start_time.elapsed().as_secs_f64();
-        p_inp = input;
         //println!("prev frame: {} fps", 1.0/prev_frame);
     }
 
@@ -191,47 +610,53 @@ pub fn main() -> Result<(), String> {
 }
 
 
-fn handle_event(event: &Event, canvas: &mut Canvas<Window>, cam: &mut Camera) -> Result<(), String> {
+/// window keys, the resize keys are left alone while text is being typed
+fn handle_event(event: &Event, canvas: &mut Canvas<Window>, screen: &mut Screen, text_input: bool) -> Result<(), String> {
+    let target = screen.get_target_size();
     match event {
+        Event::Window { win_event: WindowEvent::SizeChanged(w, h), .. } => {
+            screen.set_window_size(Vec2::new(*w as f64, *h as f64));
+        },
         Event::KeyDown {
-            keycode: Some(Keycode::Equals),
+            keycode: Some(Keycode::F11),
+            repeat: false,
             ..
         } => {
-            let mut cs = cam.get_window_size();
-            if cs.x < cam.get_view_size().x {
-                cs.x *= 2.0;
-                cs.y *= 2.0;
-            } else {
-                cs.x += cam.get_view_size().x;
-                cs.y += cam.get_view_size().y;
-            }
-            set_win_size(canvas, cam, cs)?;
+            let window = canvas.window_mut();
+            let fullscreen = match window.fullscreen_state() {
+                FullscreenType::Off => FullscreenType::Desktop,
+                _ => FullscreenType::Off,
+            };
+            window.set_fullscreen(fullscreen)?;
+        },
+        Event::KeyDown {
+            keycode: Some(Keycode::Equals),
+            ..
+        } if !text_input => {
+            let scale = screen.scale().floor() + 1.0;
+            set_win_size(canvas, Vec2::new(target.x * scale, target.y * scale))?;
         },
         Event::KeyDown {
             keycode: Some(Keycode::Minus),
             ..
-        } => {
-            let mut cs = cam.get_window_size();
-            if cs.x <= cam.get_view_size().x {
-                cs.x /= 2.0;
-                cs.y /= 2.0;
-            } else {
-                cs.x -= cam.get_view_size().x;
-                cs.y -= cam.get_view_size().y;
-            }
-            set_win_size(canvas, cam, cs)?;
+        } if !text_input => {
+            let scale = (screen.scale().ceil() - 1.0).max(1.0);
+            set_win_size(canvas, Vec2::new(target.x * scale, target.y * scale))?;
         },
         _ => {}
     }
     Ok(())
 }
 
-fn set_win_size(canvas: &mut Canvas<Window>, cam: &mut Camera, cs: Vec2) -> Result<(), String> {
+/// the new size is picked up from the resize event
+fn set_win_size(canvas: &mut Canvas<Window>, cs: Vec2) -> Result<(), String> {
+    if canvas.window().fullscreen_state() != FullscreenType::Off {
+        return Ok(());
+    }
     match canvas.window_mut().set_size(cs.x as u32, cs.y as u32) {
         Err(_) => { return Err(String::from("failed to resize window"));},
         _ => ()
     }
-    cam.set_window_size(cs);
     canvas.window_mut().set_position(
         sdl2::video::WindowPos::Centered,
         sdl2::video::WindowPos::Centered
@@ -1,7 +1,7 @@
 use std::path::Path;
 
 use tiled;
-use crate::{TextDraw, GameObject, resource, camera::Camera, Colour};
+use crate::{TextDraw, GameObject, Flip, resource, camera::{self, Camera}, Colour};
 use crate::{TextureManager, resource::Texture};
 use sdl2::render::Canvas;
 use sdl2::video::Window;
@@ -16,7 +16,7 @@ struct Tile {
 impl Tile {
     pub fn new() -> Tile {
         Tile {
-            tex: Texture{ id: 0, width: 0, height: 0},
+            tex: Texture{ id: 0, x: 0, y: 0, width: 0, height: 0},
             rect: Rect::blank(),
         }
     }
@@ -43,37 +43,55 @@ fn load_tileset(tiles: &mut Vec<Tile>, ts: &tiled::Tileset, tex: resource::Textu
     Ok(())
 }
 
+const FLIPPED_HORIZONTALLY : u32 = 0x80000000;
+const FLIPPED_VERTICALLY : u32 = 0x40000000;
+const FLIPPED_DIAGONALLY : u32 = 0x20000000;
+/// tiled also uses this bit for rotated hex tiles, which are not supported
+const ROTATED_HEXAGONAL : u32 = 0x10000000;
+
+/// split a tile id from a layer into the tile index and how it is flipped
+fn split_tile_id(id: u32) -> (usize, Flip) {
+    let flip = Flip {
+        horizontal: id & FLIPPED_HORIZONTALLY != 0,
+        vertical: id & FLIPPED_VERTICALLY != 0,
+        diagonal: id & FLIPPED_DIAGONALLY != 0,
+    };
+    let index = id & !(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY | ROTATED_HEXAGONAL);
+    (index as usize, flip)
+}
+
 struct Layer {
     tile_draws: Vec<GameObject>,
 }
 
 impl Layer {
-    fn new(l: &tiled::Layer, tiles: &Vec<Tile>) -> Layer {
+    fn new(l: &tiled::Layer, tiles: &Vec<Tile>, draw_layer: i32) -> Layer {
         let mut layer = Layer { tile_draws: Vec::new() };
         for y in 0..l.height {
             for x in 0..l.width {
-                let tile_id = l.tiles[(y * l.width + x) as usize] as usize;
+                let (tile_id, flip) = split_tile_id(l.tiles[(y * l.width + x) as usize]);
                 if tile_id == 0 { continue; }
                 let tile = &tiles[tile_id];
-                layer.tile_draws.push(
-                    GameObject::new(
-                        tile.tex,
-                        Rect::new(
-                            l.info.offset.x + (x as f64 * tile.rect.w),
-                            l.info.offset.y + (y as f64 * tile.rect.h),
-                            tile.rect.w,
-                            tile.rect.h,
-                        ),
-                        tile.rect,
-                        l.info.parallax,
-                        Colour::new(
-                            l.info.tint.r as u8,
-                            l.info.tint.g as u8,
-                            l.info.tint.b as u8,
-                            (l.info.opacity * 255.0) as u8,
-                        )
+                let mut obj = GameObject::new(
+                    tile.tex,
+                    Rect::new(
+                        l.info.offset.x + (x as f64 * tile.rect.w),
+                        l.info.offset.y + (y as f64 * tile.rect.h),
+                        tile.rect.w,
+                        tile.rect.h,
+                    ),
+                    tile.rect,
+                    l.info.parallax,
+                    Colour::new(
+                        l.info.tint.r as u8,
+                        l.info.tint.g as u8,
+                        l.info.tint.b as u8,
+                        (l.info.opacity * 255.0) as u8,
                     )
                 );
+                obj.layer = draw_layer;
+                obj.flip = flip;
+                layer.tile_draws.push(obj);
             }
         }
         layer
@@ -120,8 +138,8 @@ impl Map {
     }
 
     fn set_map_draws(&mut self) {
-        for l in self.tiled_map.layers.iter() {
-            self.layers.push(Layer::new(&l, &self.tiles));
+        for (i, l) in self.tiled_map.layers.iter().enumerate() {
+            self.layers.push(Layer::new(&l, &self.tiles, camera::layer::MAP + i as i32));
         }
     }
 }
@@ -0,0 +1,86 @@
+//! drawing without a window. Everything that draws takes a `Canvas<R>` for any sdl `RenderTarget`,
+//! so the game can be drawn to a window, or to a `Headless` surface in tests
+
+use sdl2::render::{Canvas, TextureCreator};
+use sdl2::surface::{Surface, SurfaceContext};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::image::{LoadSurface, SaveSurface};
+
+use std::path::Path;
+
+/// a software rendered canvas that draws to memory
+pub struct Headless {
+    pub canvas: Canvas<Surface<'static>>,
+}
+
+impl Headless {
+    pub fn new(width: u32, height: u32) -> Result<Headless, String> {
+        let surface = Surface::new(width, height, PixelFormatEnum::RGBA8888)?;
+        Ok(Headless { canvas: surface.into_canvas()? })
+    }
+
+    /// textures used with this canvas must be made by this creator
+    pub fn texture_creator(&self) -> TextureCreator<SurfaceContext<'static>> {
+        self.canvas.texture_creator()
+    }
+
+    /// everything drawn so far, as rows of RGBA bytes
+    pub fn pixels(&self) -> Result<Vec<u8>, String> {
+        rgba_pixels(&self.canvas.surface().convert_format(PixelFormatEnum::ABGR8888)?)
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<(), String> {
+        self.canvas.surface().convert_format(PixelFormatEnum::ABGR8888)?.save(path)
+    }
+
+    /// check what has been drawn against a golden image, each channel may be off by up to `tolerance`.
+    /// If `UPDATE_GOLDEN` is set the golden image is written instead, a missing golden image is an error
+    pub fn compare_golden(&self, path: &Path, tolerance: u8) -> Result<(), String> {
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            println!("writing golden image {}", path.to_string_lossy());
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+            }
+            return self.save_png(path);
+        }
+        if !path.exists() {
+            return Err(format!("{} doesn't exist, run with UPDATE_GOLDEN=1 to write it", path.to_string_lossy()));
+        }
+        let golden = Surface::from_file(path)?.convert_format(PixelFormatEnum::ABGR8888)?;
+        let (width, height) = self.canvas.surface().size();
+        if golden.size() != (width, height) {
+            return Err(format!("{} is {:?} but the frame is {:?}", path.to_string_lossy(), golden.size(), (width, height)));
+        }
+        let expected = rgba_pixels(&golden)?;
+        let actual = self.pixels()?;
+        let mut wrong = 0;
+        let mut first = None;
+        for (i, (a, e)) in actual.chunks(4).zip(expected.chunks(4)).enumerate() {
+            if a.iter().zip(e.iter()).any(|(a, e)| a.abs_diff(*e) > tolerance) {
+                wrong += 1;
+                first.get_or_insert((i as u32 % width, i as u32 / width, a.to_vec(), e.to_vec()));
+            }
+        }
+        match first {
+            None => Ok(()),
+            Some((x, y, a, e)) => Err(format!(
+                "{} pixels differ from {}, the first at ({}, {}) is {:?} instead of {:?}",
+                wrong, path.to_string_lossy(), x, y, a, e
+            )),
+        }
+    }
+}
+
+/// the pixels of a 32 bit surface without any row padding
+fn rgba_pixels(surface: &Surface) -> Result<Vec<u8>, String> {
+    let row = surface.width() as usize * 4;
+    let pitch = surface.pitch() as usize;
+    let height = surface.height() as usize;
+    surface.with_lock(|data| {
+        let mut pixels = Vec::with_capacity(row * height);
+        for y in 0..height {
+            pixels.extend_from_slice(&data[y * pitch..y * pitch + row]);
+        }
+        Ok(pixels)
+    })
+}
@@ -0,0 +1,226 @@
+//! a stack of game scenes (title, play, pause, ...) where only the top scene is updated
+
+use crate::input::{Input, Action};
+
+/// what the scene stack should do after a scene has updated
+pub enum Transition<G> {
+    None,
+    Push(Box<dyn Scene<G>>),
+    Pop,
+    Replace(Box<dyn Scene<G>>),
+}
+
+/// a state of the game, `G` is the data shared between all scenes
+pub trait Scene<G> {
+    /// called when the scene is pushed onto the stack
+    fn enter(&mut self, _game: &mut G) {}
+    /// called when the scene is removed from the stack
+    fn exit(&mut self, _game: &mut G) {}
+    fn update(&mut self, game: &mut G, timer: f64, input: &Input) -> Transition<G>;
+    fn draw(&self, game: &mut G) -> Result<(), String>;
+    /// if true the scene below this one is drawn first, for menus over the game
+    fn overlay(&self) -> bool { false }
+    /// if true the scene gets every button press, so restart doesn't happen while it is waiting for a binding
+    fn captures_input(&self) -> bool { false }
+}
+
+pub struct SceneStack<G> {
+    scenes: Vec<Box<dyn Scene<G>>>,
+    restart: fn() -> Box<dyn Scene<G>>,
+}
+
+impl<G> SceneStack<G> {
+    /// `restart` makes the scene the stack is reset to when the restart button is pressed
+    pub fn new(restart: fn() -> Box<dyn Scene<G>>) -> SceneStack<G> {
+        SceneStack {
+            scenes: Vec::new(),
+            restart,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+
+    pub fn push(&mut self, game: &mut G, mut scene: Box<dyn Scene<G>>) {
+        scene.enter(game);
+        self.scenes.push(scene);
+    }
+
+    pub fn pop(&mut self, game: &mut G) {
+        if let Some(mut scene) = self.scenes.pop() {
+            scene.exit(game);
+        }
+    }
+
+    pub fn replace(&mut self, game: &mut G, scene: Box<dyn Scene<G>>) {
+        self.pop(game);
+        self.push(game, scene);
+    }
+
+    /// exit every scene and start again from the restart scene
+    pub fn restart(&mut self, game: &mut G) {
+        while !self.scenes.is_empty() {
+            self.pop(game);
+        }
+        self.push(game, (self.restart)());
+    }
+
+    pub fn update(&mut self, game: &mut G, timer: f64, input: &Input) {
+        // restart is ignored while typing so its key can be used in names
+        let captured = self.scenes.last().is_some_and(|s| s.captures_input());
+        if input.pressed(Action::Restart) && input.text.is_empty() && !captured {
+            self.restart(game);
+            return;
+        }
+        let transition = match self.scenes.last_mut() {
+            Some(scene) => scene.update(game, timer, input),
+            None => return,
+        };
+        match transition {
+            Transition::None => (),
+            Transition::Push(scene) => self.push(game, scene),
+            Transition::Pop => self.pop(game),
+            Transition::Replace(scene) => self.replace(game, scene),
+        }
+    }
+
+    /// draw the top scene, and any scenes beneath it that it is overlaid on
+    pub fn draw(&self, game: &mut G) -> Result<(), String> {
+        let mut first = self.scenes.len();
+        while first > 0 {
+            first -= 1;
+            if !self.scenes[first].overlay() {
+                break;
+            }
+        }
+        for scene in self.scenes[first..].iter() {
+            scene.draw(game)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod scene_tests {
+    use super::*;
+    use crate::input::Bindings;
+    use sdl2::event::Event;
+    use sdl2::keyboard::{Scancode, Mod};
+
+    /// the game data is a log of what the scenes were asked to do
+    type Log = Vec<String>;
+
+    struct Stub {
+        name: &'static str,
+        overlay: bool,
+        captures: bool,
+        next: Option<Transition<Log>>,
+    }
+
+    fn stub(name: &'static str) -> Box<Stub> {
+        Box::new(Stub { name, overlay: false, captures: false, next: None })
+    }
+
+    fn overlay(name: &'static str) -> Box<Stub> {
+        Box::new(Stub { overlay: true, ..*stub(name) })
+    }
+
+    fn then(name: &'static str, next: Transition<Log>) -> Box<Stub> {
+        Box::new(Stub { next: Some(next), ..*stub(name) })
+    }
+
+    impl Scene<Log> for Stub {
+        fn enter(&mut self, log: &mut Log) { log.push(format!("enter {}", self.name)); }
+        fn exit(&mut self, log: &mut Log) { log.push(format!("exit {}", self.name)); }
+        fn update(&mut self, log: &mut Log, _: f64, _: &Input) -> Transition<Log> {
+            log.push(format!("update {}", self.name));
+            self.next.take().unwrap_or(Transition::None)
+        }
+        fn draw(&self, log: &mut Log) -> Result<(), String> {
+            log.push(format!("draw {}", self.name));
+            Ok(())
+        }
+        fn overlay(&self) -> bool { self.overlay }
+        fn captures_input(&self) -> bool { self.captures }
+    }
+
+    fn new_stack() -> SceneStack<Log> {
+        SceneStack::new(|| stub("restarted"))
+    }
+
+    fn restart_input() -> Input {
+        let mut input = Input::new();
+        let event = Event::KeyDown { timestamp: 0, window_id: 0, keycode: None, scancode: Some(Scancode::R), keymod: Mod::NOMOD, repeat: false };
+        input.handle_event(&event, &Bindings::new());
+        assert!(input.pressed(Action::Restart));
+        input
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let mut log = Log::new();
+        let mut stack = new_stack();
+        assert!(stack.is_empty());
+        stack.push(&mut log, stub("a"));
+        stack.push(&mut log, stub("b"));
+        stack.pop(&mut log);
+        stack.pop(&mut log);
+        stack.pop(&mut log);
+        assert!(stack.is_empty());
+        assert!(log == vec!["enter a", "enter b", "exit b", "exit a"]);
+    }
+
+    #[test]
+    fn test_transitions() {
+        let mut log = Log::new();
+        let mut stack = new_stack();
+        stack.push(&mut log, then("a", Transition::Push(then("b", Transition::Replace(then("c", Transition::Pop))))));
+        let input = Input::new();
+        for _ in 0..4 {
+            stack.update(&mut log, 0.1, &input);
+        }
+        // only the top scene is updated
+        assert!(log == vec![
+            "enter a",
+            "update a", "enter b",
+            "update b", "exit b", "enter c",
+            "update c", "exit c",
+            "update a",
+        ]);
+    }
+
+    #[test]
+    fn test_overlay_draw_order() {
+        let mut log = Log::new();
+        let mut stack = new_stack();
+        stack.push(&mut log, stub("game"));
+        stack.push(&mut log, overlay("pause"));
+        stack.push(&mut log, overlay("settings"));
+        log.clear();
+        stack.draw(&mut log).unwrap();
+        assert!(log == vec!["draw game", "draw pause", "draw settings"]);
+
+        stack.push(&mut log, stub("title"));
+        log.clear();
+        stack.draw(&mut log).unwrap();
+        assert!(log == vec!["draw title"]);
+    }
+
+    #[test]
+    fn test_restart() {
+        let mut log = Log::new();
+        let mut stack = new_stack();
+        stack.push(&mut log, stub("game"));
+        stack.push(&mut log, Box::new(Stub { captures: true, ..*stub("controls") }));
+        log.clear();
+        // a scene waiting for a binding gets the restart button instead
+        stack.update(&mut log, 0.1, &restart_input());
+        assert!(log == vec!["update controls"]);
+
+        stack.pop(&mut log);
+        log.clear();
+        stack.update(&mut log, 0.1, &restart_input());
+        assert!(log == vec!["exit game", "enter restarted"]);
+    }
+}
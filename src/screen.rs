@@ -0,0 +1,126 @@
+//! the fixed size target the game is drawn to, and how it is scaled into the window
+
+use geometry::{Rect, Vec2};
+use sdl2::event::Event;
+
+pub struct Screen {
+    target: Vec2,
+    window: Vec2,
+}
+
+impl Screen {
+    /// `target` is the resolution the game is drawn at, `window` is the size of the window
+    pub fn new(target: Vec2, window: Vec2) -> Screen {
+        Screen { target, window }
+    }
+
+    pub fn get_target_size(&self) -> Vec2 {
+        self.target
+    }
+
+    pub fn get_window_size(&self) -> Vec2 {
+        self.window
+    }
+
+    pub fn set_window_size(&mut self, size: Vec2) {
+        self.window = size;
+    }
+
+    /// the largest whole number scale that fits the target in the window,
+    /// if the window is smaller than the target it is shrunk to fit instead
+    pub fn scale(&self) -> f64 {
+        let fit = (self.window.x / self.target.x).min(self.window.y / self.target.y);
+        if fit >= 1.0 { fit.floor() } else { fit }
+    }
+
+    /// where the target is drawn in the window, centred with black bars around it
+    pub fn viewport(&self) -> Rect {
+        let scale = self.scale();
+        let size = Vec2::new(self.target.x * scale, self.target.y * scale);
+        Rect::new(
+            ((self.window.x - size.x) / 2.0).floor(),
+            ((self.window.y - size.y) / 2.0).floor(),
+            size.x,
+            size.y,
+        )
+    }
+
+    /// a window position in target pixels
+    pub fn window_to_target(&self, pos: Vec2) -> Vec2 {
+        let viewport = self.viewport();
+        let scale = self.scale();
+        Vec2::new((pos.x - viewport.x) / scale, (pos.y - viewport.y) / scale)
+    }
+
+    /// the same event with any mouse position moved from window to target pixels
+    pub fn map_event(&self, event: &Event) -> Event {
+        let mut event = event.clone();
+        match &mut event {
+            Event::MouseMotion { x, y, xrel, yrel, .. } => {
+                let pos = self.window_to_target(Vec2::new(*x as f64, *y as f64));
+                let scale = self.scale();
+                *x = pos.x.floor() as i32;
+                *y = pos.y.floor() as i32;
+                *xrel = (*xrel as f64 / scale) as i32;
+                *yrel = (*yrel as f64 / scale) as i32;
+            },
+            Event::MouseButtonDown { x, y, .. } | Event::MouseButtonUp { x, y, .. } => {
+                let pos = self.window_to_target(Vec2::new(*x as f64, *y as f64));
+                *x = pos.x.floor() as i32;
+                *y = pos.y.floor() as i32;
+            },
+            _ => (),
+        }
+        event
+    }
+}
+
+#[cfg(test)]
+mod screen_tests {
+    use super::*;
+
+    fn screen(w: f64, h: f64) -> Screen {
+        Screen::new(Vec2::new(320.0, 180.0), Vec2::new(w, h))
+    }
+
+    #[test]
+    fn test_scale() {
+        assert!(screen(1280.0, 720.0).scale() == 4.0);
+        // the tighter side picks the scale, rounded down to a whole number
+        assert!(screen(1000.0, 700.0).scale() == 3.0);
+        assert!(screen(2000.0, 400.0).scale() == 2.0);
+        assert!(screen(639.0, 1000.0).scale() == 1.0);
+        // too small to fit, so shrink
+        assert!(screen(160.0, 90.0).scale() == 0.5);
+    }
+
+    #[test]
+    fn test_viewport() {
+        let v = screen(1280.0, 720.0).viewport();
+        assert!(v.x == 0.0 && v.y == 0.0 && v.w == 1280.0 && v.h == 720.0);
+        let v = screen(1000.0, 700.0).viewport();
+        assert!(v.x == 20.0 && v.y == 80.0 && v.w == 960.0 && v.h == 540.0);
+        // odd bars put the spare pixel on the right and bottom
+        let v = screen(1001.0, 701.0).viewport();
+        assert!(v.x == 20.0 && v.y == 80.0 && v.w == 960.0 && v.h == 540.0);
+    }
+
+    #[test]
+    fn test_window_to_target() {
+        let s = screen(1001.0, 701.0);
+        let p = s.window_to_target(Vec2::new(20.0, 80.0));
+        assert!(p.x == 0.0 && p.y == 0.0);
+        let p = s.window_to_target(Vec2::new(980.0, 620.0));
+        assert!(p.x == 320.0 && p.y == 180.0);
+        let p = s.window_to_target(Vec2::new(21.5, 81.5));
+        assert!(p.x == 0.5 && p.y == 0.5);
+        // the bars are outside the target
+        let p = s.window_to_target(Vec2::new(0.0, 700.0));
+        assert!(p.x < 0.0 && p.y > 180.0);
+
+        // shrunk with an odd spare row
+        let s = screen(160.0, 91.0);
+        let p = s.window_to_target(Vec2::new(80.0, 45.0));
+        assert!(p.x == 160.0 && p.y == 90.0);
+    }
+}
@@ -0,0 +1,394 @@
+//! immediate mode ui, widgets are laid out in view units and scaled to the window when drawn
+//!
+//! call `begin` once a frame, then call widget functions in the same order each frame,
+//! the order is used to track which widget has keyboard/gamepad focus
+
+use geometry::*;
+use sdl2::pixels::Color;
+use sdl2::render::{Canvas, RenderTarget, BlendMode};
+
+use crate::{TextureManager, FontManager, TextHorizontalAlign, TextStyle, resource, camera::Camera, input::{Input, Action}};
+
+const TEXT_COLOUR : Color = Color::RGB(178, 178, 178);
+const FOCUS_TEXT_COLOUR : Color = Color::RGB(255, 255, 255);
+const FOCUS_COLOUR : Color = Color::RGBA(90, 90, 120, 255);
+const BAR_COLOUR : Color = Color::RGBA(40, 40, 50, 255);
+const BAR_FILL_COLOUR : Color = Color::RGBA(160, 160, 200, 255);
+
+/// where in the view a widget is placed relative to
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Centre,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+enum Command {
+    /// `text` is where the text is in `Ui::text`
+    Text { text: std::ops::Range<usize>, rect: Rect, align: TextHorizontalAlign, colour: Color },
+    Fill { rect: Rect, colour: Color },
+}
+
+pub struct Ui {
+    view: Vec2,
+    scale: Vec2,
+    commands: Vec<Command>,
+    /// the text of this frame's text commands, kept between frames to reuse its memory
+    text: String,
+    focus: usize,
+    widgets: usize,
+    activate: bool,
+    adjust: i32,
+    mouse: Vec2,
+    mouse_moved: bool,
+    click: bool,
+    typed: String,
+    composition: String,
+    backspaces: u32,
+    enter: bool,
+    text_focused: bool,
+}
+
+impl Default for Ui {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ui {
+    pub fn new() -> Ui {
+        Ui {
+            view: Vec2::new(1.0, 1.0),
+            scale: Vec2::new(1.0, 1.0),
+            commands: Vec::new(),
+            text: String::new(),
+            focus: 0,
+            widgets: 0,
+            activate: false,
+            adjust: 0,
+            mouse: Vec2::new(-1.0, -1.0),
+            mouse_moved: false,
+            click: false,
+            typed: String::new(),
+            composition: String::new(),
+            backspaces: 0,
+            enter: false,
+            text_focused: false,
+        }
+    }
+
+    /// clear last frame's widgets and take this frame's input
+    pub fn begin(&mut self, input: &Input, cam: &Camera) {
+        self.commands.clear();
+        self.text.clear();
+        self.view = cam.get_view_size();
+        let window = cam.get_window_size();
+        self.scale = Vec2::new(window.x / self.view.x, window.y / self.view.y);
+
+        self.typed.clear();
+        self.typed.push_str(&input.text);
+        self.composition.clear();
+        self.composition.push_str(&input.composition);
+        self.backspaces = input.backspaces;
+        self.enter = input.enter;
+        // keys bound to actions also type letters, so ignore the actions while typing into a text field
+        let typing = self.text_focused && !input.text.is_empty();
+        self.text_focused = false;
+
+        let count = self.widgets;
+        self.widgets = 0;
+        self.activate = false;
+        self.adjust = 0;
+        if count > 0 {
+            if !typing && input.pressed(Action::Up) {
+                self.focus = (self.focus + count - 1) % count;
+            }
+            if !typing && input.pressed(Action::Down) {
+                self.focus = (self.focus + 1) % count;
+            }
+            self.focus = self.focus.min(count - 1);
+        }
+        if !typing {
+            self.activate = input.pressed(Action::A);
+            if input.pressed(Action::Right) { self.adjust += 1; }
+            if input.pressed(Action::Left) { self.adjust -= 1; }
+        }
+
+        self.mouse_moved = input.mouse.moved;
+        self.mouse = Vec2::new(input.mouse.x as f64 / self.scale.x, input.mouse.y as f64 / self.scale.y);
+        self.click = input.mouse.left_pressed;
+    }
+
+    /// get a rect of the given size placed at an anchor of the view, margins are in view units
+    pub fn anchor(&self, anchor: Anchor, size: Vec2, margin: Vec2) -> Rect {
+        let x = match anchor {
+            Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft => margin.x,
+            Anchor::Top | Anchor::Centre | Anchor::Bottom => (self.view.x - size.x) / 2.0 + margin.x,
+            Anchor::TopRight | Anchor::Right | Anchor::BottomRight => self.view.x - size.x - margin.x,
+        };
+        let y = match anchor {
+            Anchor::TopLeft | Anchor::Top | Anchor::TopRight => margin.y,
+            Anchor::Left | Anchor::Centre | Anchor::Right => (self.view.y - size.y) / 2.0 + margin.y,
+            Anchor::BottomLeft | Anchor::Bottom | Anchor::BottomRight => self.view.y - size.y - margin.y,
+        };
+        Rect::new(x, y, size.x, size.y)
+    }
+
+    pub fn view_size(&self) -> Vec2 {
+        self.view
+    }
+
+    /// the index of the focused widget
+    pub fn focus(&self) -> usize {
+        self.focus
+    }
+
+    pub fn set_focus(&mut self, focus: usize) {
+        self.focus = focus;
+    }
+
+    /// a filled rect behind other widgets
+    pub fn panel(&mut self, rect: Rect, colour: Color) {
+        self.commands.push(Command::Fill { rect, colour });
+    }
+
+    /// text drawn from the left of the rect, the text height is the rect height
+    pub fn label(&mut self, text: &str, rect: Rect) {
+        self.text(text, rect, TextHorizontalAlign::Left, TEXT_COLOUR);
+    }
+
+    /// text drawn in the middle of the rect, the text height is the rect height
+    pub fn centred_label(&mut self, text: &str, rect: Rect) {
+        self.text(text, rect, TextHorizontalAlign::Center, TEXT_COLOUR);
+    }
+
+    /// returns true when the button is activated
+    pub fn button(&mut self, text: &str, rect: Rect) -> bool {
+        let (focused, active) = self.focusable(&rect);
+        self.highlight(rect, focused);
+        self.text(text, rect, TextHorizontalAlign::Center, Self::text_colour(focused));
+        active
+    }
+
+    /// flips `value` when activated or pushed left/right, returns true if it changed
+    pub fn toggle(&mut self, text: &str, rect: Rect, value: &mut bool) -> bool {
+        let (focused, active) = self.focusable(&rect);
+        let changed = active || (focused && self.adjust != 0);
+        if changed {
+            *value = !*value;
+        }
+        self.highlight(rect, focused);
+        self.text(&format!("{}: {}", text, if *value { "on" } else { "off" }),
+                  rect, TextHorizontalAlign::Center, Self::text_colour(focused));
+        changed
+    }
+
+    /// change `value` by `step` with left/right or set it by clicking the bar, returns true if it changed
+    pub fn slider(&mut self, text: &str, rect: Rect, value: &mut f64, min: f64, max: f64, step: f64) -> bool {
+        let (focused, _) = self.focusable(&rect);
+        let bar = Rect::new(rect.x + rect.w / 2.0, rect.y + rect.h / 4.0, rect.w / 2.0, rect.h / 2.0);
+        let prev = *value;
+        if focused {
+            *value += step * self.adjust as f64;
+            if self.click && rect.contains(&self.mouse) && bar.w > 0.0 {
+                let t = ((self.mouse.x - bar.x) / bar.w).clamp(0.0, 1.0);
+                *value = min + ((t * (max - min)) / step).round() * step;
+            }
+        }
+        *value = value.clamp(min, max);
+
+        self.highlight(rect, focused);
+        self.text(text, Rect::new(rect.x, rect.y, rect.w / 2.0, rect.h), TextHorizontalAlign::Left, Self::text_colour(focused));
+        self.panel(bar, BAR_COLOUR);
+        let fill = if max > min { (*value - min) / (max - min) } else { 0.0 };
+        self.panel(Rect::new(bar.x, bar.y, bar.w * fill, bar.h), BAR_FILL_COLOUR);
+        *value != prev
+    }
+
+    /// edit `value` with typed text while focused, returns true when enter is pressed
+    pub fn text_field(&mut self, rect: Rect, value: &mut String, max_chars: usize) -> bool {
+        let (focused, _) = self.focusable(&rect);
+        let mut submitted = false;
+        if focused {
+            self.text_focused = true;
+            for _ in 0..self.backspaces {
+                value.pop();
+            }
+            for c in self.typed.chars() {
+                if !c.is_control() && value.chars().count() < max_chars {
+                    value.push(c);
+                }
+            }
+            submitted = self.enter;
+        }
+        self.panel(rect, BAR_COLOUR);
+        self.highlight(rect, focused);
+        let start = self.text.len();
+        self.text.push_str(value);
+        if focused {
+            self.text.push_str(&self.composition);
+            self.text.push('_');
+        }
+        let end = self.text.len();
+        self.commands.push(Command::Text { text: start..end, rect, align: TextHorizontalAlign::Left, colour: Self::text_colour(focused) });
+        submitted
+    }
+
+    /// draw this frame's widgets, text is drawn with `font`.
+    /// Panels are alpha blended whatever the canvas blend mode is, and the blend mode is put back afterwards
+    pub fn draw<T, R: RenderTarget>(&self, canvas: &mut Canvas<R>, tm: &TextureManager<T>, fm: &mut FontManager<T>, font: &resource::Font) -> Result<(), String> {
+        let blend_mode = canvas.blend_mode();
+        canvas.set_blend_mode(BlendMode::Blend);
+        let result = self.draw_commands(canvas, tm, fm, font);
+        canvas.set_blend_mode(blend_mode);
+        result
+    }
+
+    fn draw_commands<T, R: RenderTarget>(&self, canvas: &mut Canvas<R>, tm: &TextureManager<T>, fm: &mut FontManager<T>, font: &resource::Font) -> Result<(), String> {
+        for c in self.commands.iter() {
+            match c {
+                Command::Fill { rect, colour } => {
+                    tm.draw_rect(canvas, &self.to_window(rect),
+                                 &Rect::new(colour.r as f64, colour.g as f64, colour.b as f64, colour.a as f64))?;
+                },
+                Command::Text { text, rect, align, colour } => {
+                    let rect = self.to_window(rect);
+                    let style = TextStyle { h_align: *align, ..TextStyle::new(rect.h) };
+                    fm.draw_in_rect(canvas, font, &self.text[text.clone()], &rect, &style, *colour)?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn to_window(&self, rect: &Rect) -> Rect {
+        Rect::new(rect.x * self.scale.x, rect.y * self.scale.y, rect.w * self.scale.x, rect.h * self.scale.y)
+    }
+
+    fn focusable(&mut self, rect: &Rect) -> (bool, bool) {
+        let id = self.widgets;
+        self.widgets += 1;
+        let hovered = rect.contains(&self.mouse);
+        if hovered && (self.mouse_moved || self.click) {
+            self.focus = id;
+        }
+        let focused = self.focus == id;
+        (focused, focused && (self.activate || (self.click && hovered)))
+    }
+
+    fn highlight(&mut self, rect: Rect, focused: bool) {
+        if focused {
+            self.panel(rect, FOCUS_COLOUR);
+        }
+    }
+
+    fn text(&mut self, text: &str, rect: Rect, align: TextHorizontalAlign, colour: Color) {
+        let start = self.text.len();
+        self.text.push_str(text);
+        self.commands.push(Command::Text { text: start..self.text.len(), rect, align, colour });
+    }
+
+    fn text_colour(focused: bool) -> Color {
+        if focused { FOCUS_TEXT_COLOUR } else { TEXT_COLOUR }
+    }
+}
+
+#[cfg(test)]
+mod ui_tests {
+    use super::*;
+    use crate::{render::Headless, input::Bindings};
+    use sdl2::event::Event;
+    use sdl2::keyboard::{Scancode, Mod};
+
+    fn camera() -> Camera {
+        Camera::new(Rect::new(0.0, 0.0, 240.0, 160.0), Vec2::new(480.0, 320.0))
+    }
+
+    fn key(scancode: Scancode) -> Event {
+        Event::KeyDown { timestamp: 0, window_id: 0, keycode: None, scancode: Some(scancode), keymod: Mod::NOMOD, repeat: false }
+    }
+
+    /// a frame of three buttons, with `scancode` pressed at the start of it
+    fn frame(ui: &mut Ui, scancode: Option<Scancode>) {
+        let mut input = Input::new();
+        if let Some(s) = scancode {
+            input.handle_event(&key(s), &Bindings::new());
+        }
+        ui.begin(&input, &camera());
+        for i in 0..3 {
+            ui.button("button", Rect::new(0.0, 10.0 * i as f64, 50.0, 10.0));
+        }
+    }
+
+    #[test]
+    fn test_anchor() {
+        let mut ui = Ui::new();
+        ui.begin(&Input::new(), &camera());
+        assert!(ui.view_size().x == 240.0 && ui.view_size().y == 160.0);
+        let size = Vec2::new(40.0, 20.0);
+        let margin = Vec2::new(2.0, 3.0);
+        let expected = [
+            (Anchor::TopLeft, 2.0, 3.0),
+            (Anchor::Top, 102.0, 3.0),
+            (Anchor::TopRight, 198.0, 3.0),
+            (Anchor::Left, 2.0, 73.0),
+            (Anchor::Centre, 102.0, 73.0),
+            (Anchor::Right, 198.0, 73.0),
+            (Anchor::BottomLeft, 2.0, 137.0),
+            (Anchor::Bottom, 102.0, 137.0),
+            (Anchor::BottomRight, 198.0, 137.0),
+        ];
+        for (anchor, x, y) in expected {
+            let rect = ui.anchor(anchor, size, margin);
+            assert!(rect.x == x && rect.y == y && rect.w == 40.0 && rect.h == 20.0);
+        }
+    }
+
+    #[test]
+    fn test_focus_wrap() {
+        let mut ui = Ui::new();
+        frame(&mut ui, None);
+        assert!(ui.focus() == 0);
+        frame(&mut ui, Some(Scancode::Up));
+        assert!(ui.focus() == 2);
+        frame(&mut ui, Some(Scancode::Down));
+        assert!(ui.focus() == 0);
+        frame(&mut ui, Some(Scancode::Down));
+        frame(&mut ui, Some(Scancode::Down));
+        assert!(ui.focus() == 2);
+        frame(&mut ui, Some(Scancode::Down));
+        assert!(ui.focus() == 0);
+        // focus past the last widget is pulled back when there are fewer widgets
+        ui.set_focus(7);
+        frame(&mut ui, None);
+        assert!(ui.focus() == 2);
+    }
+
+    #[test]
+    fn test_draw_blend_mode() {
+        let mut headless = Headless::new(480, 320).unwrap();
+        let texture_creator = headless.texture_creator();
+        let tm = TextureManager::new(&texture_creator);
+        let ttf = sdl2::ttf::init().unwrap();
+        let mut fm = FontManager::new(&ttf, &texture_creator).unwrap();
+        let font = fm.load_font(std::path::Path::new("textures/VT323-Regular.ttf")).unwrap();
+
+        let mut ui = Ui::new();
+        frame(&mut ui, None);
+        headless.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        headless.canvas.clear();
+        headless.canvas.set_blend_mode(BlendMode::Mul);
+        ui.draw(&mut headless.canvas, &tm, &mut fm, &font).unwrap();
+        assert!(headless.canvas.blend_mode() == BlendMode::Mul);
+        // the focused button's highlight shows on black instead of being multiplied away
+        let pixels = headless.pixels().unwrap();
+        let i = (19 * 480 + 98) * 4;
+        assert!(pixels[i..i + 3] == [FOCUS_COLOUR.r, FOCUS_COLOUR.g, FOCUS_COLOUR.b]);
+    }
+}
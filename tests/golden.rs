@@ -0,0 +1,50 @@
+//! render frames without a window and compare them to the images in `tests/golden`,
+//! run with `UPDATE_GOLDEN=1` to rewrite the images after an intended change
+
+use gudevJam12::{TextureManager, GameObject, camera::Camera, hex::HexGrid, map::Map, render::Headless};
+use geometry::{Rect, Vec2};
+
+use std::path::Path;
+
+const WIDTH : u32 = 240;
+const HEIGHT : u32 = 160;
+
+fn camera() -> Camera {
+    Camera::new(Rect::new(0.0, 0.0, WIDTH as f64, HEIGHT as f64), Vec2::new(WIDTH as f64, HEIGHT as f64))
+}
+
+fn draw_camera<T>(headless: &mut Headless, tm: &mut TextureManager<T>, cam: &mut Camera) {
+    for d in cam.drain_draws() {
+        tm.draw(&mut headless.canvas, d).unwrap();
+    }
+}
+
+#[test]
+fn hex_grid() {
+    let mut headless = Headless::new(WIDTH, HEIGHT).unwrap();
+    let texture_creator = headless.texture_creator();
+    let mut tm = TextureManager::new(&texture_creator);
+    let bg = GameObject::new_from_tex(tm.load(Path::new("textures/bg.png")).unwrap());
+    let hex_grid = HexGrid::new(&mut tm).unwrap();
+
+    let mut cam = camera();
+    cam.add_cam_space(&bg);
+    hex_grid.draw(&mut cam);
+    draw_camera(&mut headless, &mut tm, &mut cam);
+
+    headless.compare_golden(Path::new("tests/golden/hex_grid.png"), 0).unwrap();
+}
+
+#[test]
+fn map() {
+    let mut headless = Headless::new(WIDTH, HEIGHT).unwrap();
+    let texture_creator = headless.texture_creator();
+    let mut tm = TextureManager::new(&texture_creator);
+    let map = Map::new("test-resources/test.tmx", &mut tm).unwrap();
+
+    let mut cam = camera();
+    map.draw(&mut cam);
+    draw_camera(&mut headless, &mut tm, &mut cam);
+
+    headless.compare_golden(Path::new("tests/golden/map.png"), 0).unwrap();
+}